@@ -0,0 +1,381 @@
+//! Player activity, the actions per minute (APM) and the effective actions per minute (EPM).
+//! The actions are the commands, selections, control groups and camera hotkeys the players issue,
+//! the camera movements and UI triggers are not counted.
+//! An action is not effective when it repeats the previous action of the player too quickly,
+//! i.e. selecting the same units, recalling the same control group or ordering the same target.
+//! The commands without a target, i.e. queueing units or research, are always effective.
+//!
+//! The ActivityTracker is a ReplaySink on its own, so it can be used for post-game reports:
+//! ```no_run
+//! use swarmy::*;
+//! let sc2_rerun = SC2Rerun::new("assets/Burrow.SC2Replay", Default::default()).unwrap();
+//! let mut activity_tracker = ActivityTracker::default();
+//! sc2_rerun.add_events(&mut activity_tracker).unwrap();
+//! println!("{}", activity_tracker.to_markdown());
+//! ```
+
+use super::*;
+use s2protocol::game_events::{GameSCmdData, ReplayGameEvent};
+use std::collections::{BTreeMap, VecDeque};
+
+/// How the activity is measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivityConfig {
+    /// The seconds of actions the rolling APM and EPM are computed over.
+    pub window_secs: f32,
+    /// An action that repeats the previous one within these seconds is not effective.
+    pub spam_secs: f32,
+}
+
+impl Default for ActivityConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 60.,
+            spam_secs: 0.5,
+        }
+    }
+}
+
+/// The actions of a player.
+#[derive(Debug, Default, Clone)]
+struct PlayerActivity {
+    /// The number of actions in the game.
+    actions: u32,
+    /// The number of effective actions in the game.
+    effective_actions: u32,
+    /// The game loop of the actions within the window, and whether they were effective.
+    recent_actions: VecDeque<(i64, bool)>,
+    /// The game loop and kind of the last action.
+    last_action: Option<(i64, String)>,
+    /// The highest rolling APM and EPM.
+    peak: (f64, f64),
+}
+
+impl PlayerActivity {
+    /// Drops the actions out of the window, returns the APM and EPM of the ones left.
+    fn rolling(&mut self, game_loop: i64, window_loops: i64, window_minutes: f64) -> (f64, f64) {
+        while self
+            .recent_actions
+            .front()
+            .is_some_and(|(action_loop, _)| *action_loop <= game_loop - window_loops)
+        {
+            self.recent_actions.pop_front();
+        }
+        let effective_actions = self
+            .recent_actions
+            .iter()
+            .filter(|(_, is_effective)| *is_effective)
+            .count();
+        (
+            self.recent_actions.len() as f64 / window_minutes,
+            effective_actions as f64 / window_minutes,
+        )
+    }
+}
+
+/// The activity of a player over the whole game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivitySummary {
+    pub player_id: u8,
+    /// The number of actions in the game.
+    pub actions: u32,
+    /// The number of effective actions in the game.
+    pub effective_actions: u32,
+    /// The average actions per minute.
+    pub apm: f64,
+    /// The average effective actions per minute.
+    pub epm: f64,
+    /// The highest rolling actions per minute.
+    pub peak_apm: f64,
+    /// The highest rolling effective actions per minute.
+    pub peak_epm: f64,
+}
+
+/// Counts the actions of the players as the replay is stepped through.
+#[derive(Debug, Clone, Default)]
+pub struct ActivityTracker {
+    /// How the activity is measured.
+    pub config: ActivityConfig,
+    /// The replay metadata, to map the game events user ids to player ids.
+    summary: ReplaySummary,
+    /// The actions by player id.
+    players: BTreeMap<u8, PlayerActivity>,
+    /// The game loop of the last game event.
+    game_loop: i64,
+}
+
+impl ActivityTracker {
+    /// Sets how the activity is measured.
+    pub fn with_config(mut self, config: ActivityConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn secs_to_loops(&self, secs: f32) -> i64 {
+        (secs as f64 * self.summary.game_speed.game_loops_per_second()) as i64
+    }
+
+    /// Returns the kind of action a game event is along its payload, used to tell repeated
+    /// actions apart. The commands without a target carry their sequence, so that each unit or
+    /// research queued is a different action. Events that are not actions, i.e. camera
+    /// movements, return None.
+    pub fn action_kind(game_event: &ReplayGameEvent) -> Option<String> {
+        match game_event {
+            ReplayGameEvent::Cmd(game_cmd) => {
+                let target = match &game_cmd.m_data {
+                    GameSCmdData::TargetPoint(target) => format!("Point/{}/{}", target.x, target.y),
+                    GameSCmdData::TargetUnit(target) => format!("Unit/{}", target.m_tag),
+                    GameSCmdData::Data(data) => format!("Data/{}", data),
+                    GameSCmdData::None => format!("Sequence/{}", game_cmd.m_sequence),
+                };
+                let (ability, cmd_index) = game_cmd
+                    .m_abil
+                    .as_ref()
+                    .map(|abil| (abil.ability.as_str(), abil.m_abil_cmd_index))
+                    .unwrap_or_default();
+                Some(format!("Cmd/{}/{}/{}", ability, cmd_index, target))
+            }
+            ReplayGameEvent::CmdUpdateTargetPoint(target_point) => Some(format!(
+                "CmdUpdateTarget/Point/{}/{}",
+                target_point.m_target.x, target_point.m_target.y
+            )),
+            ReplayGameEvent::CmdUpdateTargetUnit(target_unit) => Some(format!(
+                "CmdUpdateTarget/Unit/{}",
+                target_unit.m_target.m_tag
+            )),
+            ReplayGameEvent::SelectionDelta(selection_delta) => Some(format!(
+                "SelectionDelta/{}/{:?}",
+                selection_delta.m_control_group_id, selection_delta.m_delta
+            )),
+            ReplayGameEvent::ControlGroupUpdate(ctrl_group) => Some(format!(
+                "ControlGroup/{}/{:?}",
+                ctrl_group.m_control_group_index, ctrl_group.m_control_group_update
+            )),
+            ReplayGameEvent::CameraSave(camera_save) => {
+                Some(format!("CameraSave/{}", camera_save.m_which))
+            }
+            _ => None,
+        }
+    }
+
+    /// Adds an action of a player, it is effective unless it repeats their previous action
+    /// within the spam seconds.
+    pub fn add_action(&mut self, player_id: u8, action_kind: String, game_loop: i64) {
+        let spam_loops = self.secs_to_loops(self.config.spam_secs);
+        let window_loops = self.secs_to_loops(self.config.window_secs);
+        let window_minutes = self.config.window_secs as f64 / 60.;
+        let player = self.players.entry(player_id).or_default();
+        let is_effective = !player
+            .last_action
+            .as_ref()
+            .is_some_and(|(last_loop, last_kind)| {
+                *last_kind == action_kind && game_loop - last_loop <= spam_loops
+            });
+        player.actions += 1;
+        if is_effective {
+            player.effective_actions += 1;
+        }
+        player.recent_actions.push_back((game_loop, is_effective));
+        player.last_action = Some((game_loop, action_kind));
+        let (apm, epm) = player.rolling(game_loop, window_loops, window_minutes);
+        player.peak = (player.peak.0.max(apm), player.peak.1.max(epm));
+    }
+
+    /// Returns the rolling APM and EPM of each player, the actions in the window before the game
+    /// loop scaled to a minute.
+    pub fn rolling(&mut self, game_loop: i64) -> Vec<(u8, f64, f64)> {
+        let window_loops = self.secs_to_loops(self.config.window_secs);
+        let window_minutes = self.config.window_secs as f64 / 60.;
+        self.players
+            .iter_mut()
+            .map(|(player_id, player)| {
+                let (apm, epm) = player.rolling(game_loop, window_loops, window_minutes);
+                (*player_id, apm, epm)
+            })
+            .collect()
+    }
+
+    /// The activity of each player over the game so far.
+    pub fn summaries(&self) -> Vec<ActivitySummary> {
+        let minutes = (self.summary.game_speed.game_loop_secs(self.game_loop) / 60.).max(1. / 60.);
+        self.players
+            .iter()
+            .map(|(player_id, player)| ActivitySummary {
+                player_id: *player_id,
+                actions: player.actions,
+                effective_actions: player.effective_actions,
+                apm: player.actions as f64 / minutes,
+                epm: player.effective_actions as f64 / minutes,
+                peak_apm: player.peak.0,
+                peak_epm: player.peak.1,
+            })
+            .collect()
+    }
+
+    /// A markdown table of the activity of each player, to be shown in the viewer.
+    pub fn to_markdown(&self) -> String {
+        let mut res = String::from("# Activity\n\n");
+        res.push_str("| Player | Name | Actions | APM | EPM | Peak APM | Peak EPM |\n");
+        res.push_str("|---|---|---|---|---|---|---|\n");
+        for activity in self.summaries() {
+            let name = self
+                .summary
+                .player_by_id(activity.player_id)
+                .map(|player| player.name.as_str())
+                .unwrap_or_default();
+            res.push_str(&format!(
+                "| {} | {} | {} | {:.0} | {:.0} | {:.0} | {:.0} |\n",
+                activity.player_id,
+                name,
+                activity.actions,
+                activity.apm,
+                activity.epm,
+                activity.peak_apm,
+                activity.peak_epm
+            ));
+        }
+        res
+    }
+}
+
+impl ReplaySink for ActivityTracker {
+    fn register_summary(&mut self, summary: &ReplaySummary) -> Result<(), SwarmyError> {
+        self.summary = summary.clone();
+        Ok(())
+    }
+
+    fn register_game_event(
+        &mut self,
+        user_id: i64,
+        game_event: &ReplayGameEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.game_loop = game_loop;
+        // The actions of the observers are not counted.
+        if let (Some(action_kind), Some(player_id)) = (
+            Self::action_kind(game_event),
+            self.summary.user_player_id(user_id),
+        ) {
+            self.add_action(player_id, action_kind, game_loop);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use s2protocol::game_events::{
+        GameSCmdAbil, GameSCmdEvent, GameSMapCoord3D, GameSSelectionDelta,
+        GameSSelectionDeltaEvent, GameSSelectionMask,
+    };
+
+    fn cmd(ability: &str, m_data: GameSCmdData, m_sequence: i64) -> ReplayGameEvent {
+        ReplayGameEvent::Cmd(GameSCmdEvent {
+            m_cmd_flags: 0,
+            m_abil: Some(GameSCmdAbil {
+                m_abil_link: 0,
+                ability: ability.to_string(),
+                m_abil_cmd_index: 0,
+                m_abil_cmd_data: None,
+            }),
+            m_data,
+            m_sequence,
+            m_other_unit: None,
+            m_unit_group: None,
+        })
+    }
+
+    fn target_point(x: i64, y: i64) -> GameSCmdData {
+        GameSCmdData::TargetPoint(GameSMapCoord3D { x, y, z: 0 })
+    }
+
+    fn select(unit_tags: Vec<u32>) -> ReplayGameEvent {
+        ReplayGameEvent::SelectionDelta(GameSSelectionDeltaEvent {
+            m_control_group_id: 10,
+            m_delta: GameSSelectionDelta {
+                m_subgroup_index: 0,
+                m_remove_mask: GameSSelectionMask::None,
+                m_add_subgroups: vec![],
+                m_add_unit_tags: unit_tags,
+            },
+        })
+    }
+
+    /// Adds the game events as actions of player 1, returns their effective actions.
+    fn effective_actions(game_events: &[(ReplayGameEvent, i64)]) -> u32 {
+        let mut activity_tracker = ActivityTracker::default();
+        for (game_event, game_loop) in game_events {
+            let action_kind = ActivityTracker::action_kind(game_event).unwrap();
+            activity_tracker.add_action(1, action_kind, *game_loop);
+        }
+        activity_tracker.summaries()[0].effective_actions
+    }
+
+    #[test]
+    fn it_filters_the_repeated_actions_within_the_spam_seconds() {
+        // The spam seconds are 11 game loops at Faster speed.
+        assert_eq!(ActivityTracker::default().secs_to_loops(0.5), 11);
+        let selection = select(vec![1, 2]);
+        assert_eq!(
+            effective_actions(&[
+                (selection.clone(), 0),
+                (selection.clone(), 5),
+                (selection.clone(), 16),
+                (selection.clone(), 28),
+            ]),
+            2
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_repeated_actions_with_a_different_payload() {
+        // Selecting different units, moving to different points and queueing several units.
+        assert_eq!(
+            effective_actions(&[
+                (select(vec![1]), 0),
+                (select(vec![2]), 1),
+                (cmd("Move", target_point(100, 100), 1), 2),
+                (cmd("Move", target_point(200, 100), 2), 3),
+                (cmd("Move", target_point(200, 100), 3), 4),
+                (cmd("BarracksTrain", GameSCmdData::None, 4), 5),
+                (cmd("BarracksTrain", GameSCmdData::None, 5), 6),
+            ]),
+            6
+        );
+    }
+
+    #[test]
+    fn it_measures_the_rolling_apm_and_epm_over_the_window() {
+        let mut activity_tracker = ActivityTracker::default().with_config(ActivityConfig {
+            window_secs: 30.,
+            spam_secs: 0.5,
+        });
+        let window_loops = activity_tracker.secs_to_loops(30.);
+        assert_eq!(window_loops, 672);
+        activity_tracker.add_action(1, "A".to_string(), 0);
+        activity_tracker.add_action(1, "A".to_string(), 1);
+        activity_tracker.add_action(1, "B".to_string(), 100);
+        activity_tracker.add_action(2, "A".to_string(), 100);
+        // Half a minute window, so each action is 2 per minute.
+        assert_eq!(
+            activity_tracker.rolling(window_loops - 1),
+            vec![(1, 6., 4.), (2, 2., 2.)]
+        );
+        // The first two actions leave the window.
+        assert_eq!(
+            activity_tracker.rolling(window_loops + 1),
+            vec![(1, 2., 2.), (2, 2., 2.)]
+        );
+        assert_eq!(
+            activity_tracker.rolling(window_loops + 100),
+            vec![(1, 0., 0.), (2, 0., 0.)]
+        );
+        let summaries = activity_tracker.summaries();
+        assert_eq!(
+            (summaries[0].actions, summaries[0].effective_actions),
+            (3, 2)
+        );
+        assert_eq!((summaries[0].peak_apm, summaries[0].peak_epm), (6., 4.));
+    }
+}
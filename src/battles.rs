@@ -0,0 +1,404 @@
+//! Battle detection, the unit deaths are clustered in space and time into engagements.
+//! A death is part of a battle when it happens close to one of its recent deaths, so that a
+//! battle follows the armies as they move. Only the deaths of units killed by a player are
+//! considered, so mined out mineral fields or expiring broodlings do not start battles.
+//!
+//! The BattleDetector is a ReplaySink on its own, so it can be used for post-game reports:
+//! ```no_run
+//! use swarmy::*;
+//! let sc2_rerun = SC2Rerun::new("assets/Burrow.SC2Replay", Default::default()).unwrap();
+//! let mut battle_detector = BattleDetector::new(UnitCatalog::bundled());
+//! sc2_rerun.add_events(&mut battle_detector).unwrap();
+//! for battle in battle_detector.battles() {
+//!     println!("{:?}", battle);
+//! }
+//! ```
+
+use super::*;
+use s2protocol::SC2Unit;
+use std::collections::BTreeMap;
+
+/// When deaths are considered part of the same battle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BattleConfig {
+    /// How far from a recent death of the battle a death can be, in map units.
+    pub radius: f32,
+    /// How long a battle can go without deaths before it is over.
+    pub max_gap_secs: f32,
+    /// The minimum number of deaths for a cluster to be reported as a battle.
+    pub min_deaths: u32,
+}
+
+impl Default for BattleConfig {
+    fn default() -> Self {
+        Self {
+            radius: 10.,
+            max_gap_secs: 10.,
+            min_deaths: 4,
+        }
+    }
+}
+
+/// What a player lost in a battle.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BattleLosses {
+    /// The number of units and structures lost.
+    pub units: u32,
+    /// The minerals spent on the lost units.
+    pub minerals: u32,
+    /// The gas spent on the lost units.
+    pub gas: u32,
+    /// The supply of the lost units.
+    pub supply: f32,
+}
+
+/// An engagement, a cluster of deaths in space and time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Battle {
+    /// The game loop of the first death.
+    pub start_loop: i64,
+    /// The game loop of the last death.
+    pub end_loop: i64,
+    /// The bottom left corner of the area where the deaths happened.
+    pub min: (f32, f32),
+    /// The top right corner of the area where the deaths happened.
+    pub max: (f32, f32),
+    /// The losses by player id.
+    pub losses: BTreeMap<u8, BattleLosses>,
+    /// The game loop and position of each death.
+    pub death_points: Vec<(i64, (f32, f32))>,
+}
+
+impl Battle {
+    fn new(pos: (f32, f32), game_loop: i64) -> Self {
+        Self {
+            start_loop: game_loop,
+            end_loop: game_loop,
+            min: pos,
+            max: pos,
+            losses: BTreeMap::new(),
+            death_points: vec![],
+        }
+    }
+
+    /// The number of deaths in the battle.
+    pub fn deaths(&self) -> u32 {
+        self.losses.values().map(|losses| losses.units).sum()
+    }
+
+    /// The center of the area of the battle.
+    pub fn center(&self) -> (f32, f32) {
+        (
+            (self.min.0 + self.max.0) / 2.,
+            (self.min.1 + self.max.1) / 2.,
+        )
+    }
+
+    /// The half of the width and height of the area of the battle.
+    pub fn half_size(&self) -> (f32, f32) {
+        (
+            (self.max.0 - self.min.0) / 2.,
+            (self.max.1 - self.min.1) / 2.,
+        )
+    }
+
+    /// Whether a position is within a distance of a death that happened since a game loop.
+    fn is_near(&self, pos: (f32, f32), radius: f32, since_loop: i64) -> bool {
+        self.death_points
+            .iter()
+            .rev()
+            .take_while(|(game_loop, _)| *game_loop >= since_loop)
+            .any(|(_, death_pos)| {
+                (death_pos.0 - pos.0).powi(2) + (death_pos.1 - pos.1).powi(2) <= radius.powi(2)
+            })
+    }
+
+    fn add_death(
+        &mut self,
+        player_id: u8,
+        entry: Option<&UnitCatalogEntry>,
+        pos: (f32, f32),
+        game_loop: i64,
+    ) {
+        self.end_loop = game_loop;
+        self.death_points.push((game_loop, pos));
+        self.min = (self.min.0.min(pos.0), self.min.1.min(pos.1));
+        self.max = (self.max.0.max(pos.0), self.max.1.max(pos.1));
+        let losses = self.losses.entry(player_id).or_default();
+        losses.units += 1;
+        if let Some(entry) = entry {
+            losses.minerals += entry.minerals;
+            losses.gas += entry.gas;
+            losses.supply += entry.supply;
+        }
+    }
+
+    /// A one line description of the battle, i.e.
+    /// `05:12-05:40 P1 lost 12 units (600m 250g 14 supply), P2 lost 3 units (...)`
+    pub fn summary_text(&self, game_speed: GameSpeed) -> String {
+        let format_time = |game_loop: i64| {
+            let secs = game_speed.game_loop_secs(game_loop) as i64;
+            format!("{:02}:{:02}", secs / 60, secs % 60)
+        };
+        let losses: Vec<String> = self
+            .losses
+            .iter()
+            .map(|(player_id, losses)| {
+                format!(
+                    "P{} lost {} units ({}m {}g {} supply)",
+                    player_id, losses.units, losses.minerals, losses.gas, losses.supply
+                )
+            })
+            .collect();
+        format!(
+            "{}-{} {}",
+            format_time(self.start_loop),
+            format_time(self.end_loop),
+            losses.join(", ")
+        )
+    }
+}
+
+/// Clusters the unit deaths into battles as the replay is stepped through.
+#[derive(Debug, Clone)]
+pub struct BattleDetector {
+    /// When deaths are considered part of the same battle.
+    pub config: BattleConfig,
+    /// The unit catalog, for the cost of the lost units.
+    unit_catalog: UnitCatalog,
+    /// The game speed, to turn the max gap into game loops.
+    game_speed: GameSpeed,
+    /// The battles that may still get more deaths.
+    active: Vec<Battle>,
+    /// The battles that are over, in the order they ended.
+    battles: Vec<Battle>,
+}
+
+impl BattleDetector {
+    pub fn new(unit_catalog: UnitCatalog) -> Self {
+        Self {
+            config: BattleConfig::default(),
+            unit_catalog,
+            game_speed: GameSpeed::default(),
+            active: vec![],
+            battles: vec![],
+        }
+    }
+
+    /// Sets when deaths are considered part of the same battle.
+    pub fn with_config(mut self, config: BattleConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The battles that are over.
+    pub fn battles(&self) -> &[Battle] {
+        &self.battles
+    }
+
+    fn max_gap_loops(&self) -> i64 {
+        (self.config.max_gap_secs as f64 * self.game_speed.game_loops_per_second()) as i64
+    }
+
+    /// Adds a death to the battle it is near of, or starts a new battle.
+    /// Returns the battles that ended before this death.
+    pub fn add_death(
+        &mut self,
+        killed: &SC2Unit,
+        unit_dead: &UnitDiedEvent,
+        game_loop: i64,
+    ) -> Vec<Battle> {
+        let since_loop = game_loop - self.max_gap_loops();
+        let ended = self.close_battles(since_loop);
+        if unit_dead.killer_player_id.is_none() {
+            return ended;
+        }
+        let entry = self.unit_catalog.units.get(&killed.name);
+        if matches!(
+            entry.map(|entry| entry.category),
+            None | Some(UnitCategory::Resource) | Some(UnitCategory::Neutral)
+        ) {
+            return ended;
+        }
+        let pos = (unit_dead.x as f32, unit_dead.y as f32);
+        let radius = self.config.radius;
+        let battle_idx = match self
+            .active
+            .iter()
+            .position(|battle| battle.is_near(pos, radius, since_loop))
+        {
+            Some(battle_idx) => battle_idx,
+            None => {
+                self.active.push(Battle::new(pos, game_loop));
+                self.active.len() - 1
+            }
+        };
+        self.active[battle_idx].add_death(killed.user_id.unwrap_or(99u8), entry, pos, game_loop);
+        ended
+    }
+
+    /// Ends the battles with no deaths since the given game loop.
+    /// Returns the ones with enough deaths to be reported.
+    pub fn close_battles(&mut self, before_loop: i64) -> Vec<Battle> {
+        let (ended, active): (Vec<Battle>, Vec<Battle>) = self
+            .active
+            .drain(..)
+            .partition(|battle| battle.end_loop < before_loop);
+        self.active = active;
+        let ended: Vec<Battle> = ended
+            .into_iter()
+            .filter(|battle| battle.deaths() >= self.config.min_deaths)
+            .collect();
+        self.battles.extend(ended.iter().cloned());
+        ended
+    }
+}
+
+impl ReplaySink for BattleDetector {
+    fn register_summary(&mut self, summary: &ReplaySummary) -> Result<(), SwarmyError> {
+        self.game_speed = summary.game_speed;
+        Ok(())
+    }
+
+    fn register_unit_died(
+        &mut self,
+        unit_dead: &UnitDiedEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        if let UnitChangeHint::Unregistered { killed, .. } = change_hint {
+            self.add_death(&killed, unit_dead, tracker_loop);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SwarmyError> {
+        self.close_battles(i64::MAX);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> BattleDetector {
+        BattleDetector::new(UnitCatalog::bundled()).with_config(BattleConfig {
+            radius: 5.,
+            max_gap_secs: 10.,
+            min_deaths: 2,
+        })
+    }
+
+    /// Adds the death of a unit of a player, killed by another player unless killer is None.
+    fn add_death(
+        battle_detector: &mut BattleDetector,
+        user_id: u8,
+        unit_name: &str,
+        pos: (u8, u8),
+        game_loop: i64,
+        killer_player_id: Option<u8>,
+    ) -> Vec<Battle> {
+        let killed = SC2Unit {
+            user_id: Some(user_id),
+            name: unit_name.to_string(),
+            ..Default::default()
+        };
+        let unit_dead = UnitDiedEvent {
+            killer_player_id,
+            x: pos.0,
+            y: pos.1,
+            ..Default::default()
+        };
+        battle_detector.add_death(&killed, &unit_dead, game_loop)
+    }
+
+    #[test]
+    fn it_merges_the_deaths_near_a_recent_death() {
+        let mut battle_detector = detector();
+        // The third death is out of the radius of the first one but near the second one.
+        add_death(&mut battle_detector, 1, "Marine", (10, 10), 0, Some(2));
+        add_death(&mut battle_detector, 2, "Stalker", (14, 10), 100, Some(1));
+        add_death(&mut battle_detector, 1, "Marine", (18, 10), 200, Some(2));
+        battle_detector.finish().unwrap();
+        let battles = battle_detector.battles();
+        assert_eq!(battles.len(), 1);
+        let battle = &battles[0];
+        assert_eq!((battle.start_loop, battle.end_loop), (0, 200));
+        assert_eq!((battle.min, battle.max), ((10., 10.), (18., 10.)));
+        assert_eq!(battle.center(), (14., 10.));
+        assert_eq!(battle.deaths(), 3);
+        assert_eq!(
+            battle.losses[&1],
+            BattleLosses {
+                units: 2,
+                minerals: 100,
+                gas: 0,
+                supply: 2.,
+            }
+        );
+        assert_eq!(
+            battle.losses[&2],
+            BattleLosses {
+                units: 1,
+                minerals: 125,
+                gas: 50,
+                supply: 2.,
+            }
+        );
+    }
+
+    #[test]
+    fn it_splits_the_deaths_apart_in_space_or_time() {
+        let mut battle_detector = detector();
+        let max_gap_loops = battle_detector.max_gap_loops();
+        assert_eq!(max_gap_loops, 224);
+        // Two battles at the same time in different places.
+        add_death(&mut battle_detector, 1, "Marine", (10, 10), 0, Some(2));
+        add_death(&mut battle_detector, 1, "Marine", (60, 60), 10, Some(2));
+        add_death(&mut battle_detector, 1, "Marine", (11, 10), 20, Some(2));
+        add_death(&mut battle_detector, 1, "Marine", (60, 61), 30, Some(2));
+        // A battle ends once a death happens more than the max gap after its last death.
+        let mut add_later_death = |game_loop| {
+            add_death(
+                &mut battle_detector,
+                2,
+                "Zergling",
+                (100, 100),
+                game_loop,
+                Some(1),
+            )
+        };
+        assert!(add_later_death(20 + max_gap_loops).is_empty());
+        let ended = add_later_death(31 + max_gap_loops);
+        assert_eq!(ended.len(), 2);
+        assert_eq!((ended[0].min, ended[0].deaths()), ((10., 10.), 2));
+        assert_eq!((ended[1].min, ended[1].deaths()), ((60., 60.), 2));
+        battle_detector.finish().unwrap();
+        let battles = battle_detector.battles();
+        assert_eq!(battles.len(), 3);
+        assert_eq!(battles[2].start_loop, 20 + max_gap_loops);
+        assert_eq!(battles[2].losses[&2].units, 2);
+    }
+
+    #[test]
+    fn it_skips_the_small_clusters_and_the_deaths_not_killed_by_a_player() {
+        let mut battle_detector = detector();
+        // A single death is not a battle.
+        add_death(&mut battle_detector, 1, "Marine", (100, 100), 0, Some(2));
+        // Neither the deaths without a killer nor the resources count.
+        add_death(&mut battle_detector, 1, "Marine", (10, 10), 0, None);
+        add_death(&mut battle_detector, 1, "Marine", (10, 10), 10, None);
+        add_death(
+            &mut battle_detector,
+            0,
+            "LabMineralField",
+            (10, 10),
+            20,
+            Some(1),
+        );
+        add_death(&mut battle_detector, 1, "Marine", (10, 10), 30, Some(2));
+        battle_detector.finish().unwrap();
+        assert!(battle_detector.battles().is_empty());
+    }
+}
@@ -0,0 +1,365 @@
+//! Build order, the structures, units, morphs and upgrades each player made, in order.
+//! The steps are taken from the tracker events, so their timing is the one the game reports:
+//! structures are listed when they are started, units when they are born, or when the warp-in
+//! starts for the Protoss, morphs when the unit changes into the more expensive type and
+//! upgrades when they are done.
+//! The supply of a step is the supply of the units the player had before it, counted from the
+//! unit catalog, the units in production are not counted.
+//!
+//! The BuildOrderExtractor is a ReplaySink on its own, so it can be used to export the build
+//! orders as JSON:
+//! ```no_run
+//! use swarmy::*;
+//! let sc2_rerun = SC2Rerun::new("assets/Burrow.SC2Replay", Default::default()).unwrap();
+//! let mut build_order_extractor = BuildOrderExtractor::new(UnitCatalog::bundled());
+//! sc2_rerun.add_events(&mut build_order_extractor).unwrap();
+//! println!("{}", build_order_extractor.to_json().unwrap());
+//! ```
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Units that are made by other units and would flood the build order.
+const IGNORED_UNITS: [&str; 1] = ["Interceptor"];
+
+/// The upgrades that are cosmetic, i.e. sprays, these are not part of the build order.
+pub const COSMETIC_UPGRADE_PREFIXES: [&str; 3] = ["Spray", "RewardDance", "GameHeart"];
+
+/// The cocoons and eggs of the morphs, listed as the unit they morph into.
+const MORPH_COCOONS: [(&str, &str); 6] = [
+    ("BanelingCocoon", "Baneling"),
+    ("RavagerCocoon", "Ravager"),
+    ("LurkerMPEgg", "LurkerMP"),
+    ("BroodLordCocoon", "BroodLord"),
+    ("OverlordCocoon", "Overseer"),
+    ("TransportOverlordCocoon", "OverlordTransport"),
+];
+
+/// What a build order step made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BuildOrderStepKind {
+    Structure,
+    Unit,
+    Morph,
+    Upgrade,
+}
+
+/// A structure, unit, morph or upgrade made by a player.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildOrderStep {
+    /// The supply of the player before the step.
+    pub supply: u32,
+    /// The game loop of the step.
+    pub game_loop: i64,
+    /// The game time of the step in seconds.
+    pub game_secs: f64,
+    /// The name of the structure, unit or upgrade, i.e. `SpawningPool`
+    pub item: String,
+    pub kind: BuildOrderStepKind,
+}
+
+impl BuildOrderStep {
+    /// The game time of the step as `m:ss`
+    pub fn game_time(&self) -> String {
+        let secs = self.game_secs as u64;
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// The build order of a player.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildOrder {
+    pub player_id: u8,
+    pub name: String,
+    pub race: String,
+    /// The steps sorted by game loop.
+    pub steps: Vec<BuildOrderStep>,
+}
+
+impl BuildOrder {
+    /// Reads the build orders from a JSON file, as written by `--build-order-json`.
+    pub fn from_file(path: &str) -> Result<Vec<Self>, SwarmyError> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// A markdown table of the steps, to be shown in the viewer.
+    pub fn to_markdown(&self) -> String {
+        let mut res = format!("# Build order of {} ({})\n\n", self.name, self.race);
+        res.push_str("| Supply | Time | Item | Kind |\n");
+        res.push_str("|---|---|---|---|\n");
+        for step in &self.steps {
+            res.push_str(&format!(
+                "| {} | {} | {} | {:?} |\n",
+                step.supply,
+                step.game_time(),
+                step.item,
+                step.kind
+            ));
+        }
+        res
+    }
+}
+
+/// Collects the build order of each player as the replay is stepped through.
+#[derive(Debug, Clone)]
+pub struct BuildOrderExtractor {
+    /// The unit catalog, for the cost and supply of the units.
+    unit_catalog: UnitCatalog,
+    /// The replay metadata, for the names of the players and the game speed.
+    summary: ReplaySummary,
+    /// The owner and type of the alive units, by unit tag.
+    units: HashMap<i64, (u8, String)>,
+    /// The supply of the alive units of each player.
+    supply: BTreeMap<u8, f32>,
+    /// The build orders by player id.
+    build_orders: BTreeMap<u8, BuildOrder>,
+}
+
+impl BuildOrderExtractor {
+    pub fn new(unit_catalog: UnitCatalog) -> Self {
+        Self {
+            unit_catalog,
+            summary: ReplaySummary::default(),
+            units: HashMap::new(),
+            supply: BTreeMap::new(),
+            build_orders: BTreeMap::new(),
+        }
+    }
+
+    /// The build order of each player, sorted by player id.
+    pub fn build_orders(&self) -> Vec<BuildOrder> {
+        self.build_orders.values().cloned().collect()
+    }
+
+    /// The build orders of the players as a JSON array.
+    pub fn to_json(&self) -> Result<String, SwarmyError> {
+        Ok(serde_json::to_string_pretty(&self.build_orders())?)
+    }
+
+    /// Returns the minerals and gas spent on a unit type, 0 for unknown types.
+    fn cost(&self, unit_name: &str) -> u32 {
+        self.unit_catalog
+            .units
+            .get(unit_name)
+            .map(|entry| entry.minerals + entry.gas)
+            .unwrap_or_default()
+    }
+
+    /// Returns the supply a unit type takes, 0 for unknown types.
+    fn unit_supply(&self, unit_name: &str) -> f32 {
+        self.unit_catalog
+            .units
+            .get(unit_name)
+            .map(|entry| entry.supply)
+            .unwrap_or_default()
+    }
+
+    /// Sets the owner and type of a unit and updates the supply of its owner.
+    fn set_unit(&mut self, unit_tag: i64, player_id: u8, unit_name: &str) {
+        if let Some((previous_player_id, previous_name)) = self.units.remove(&unit_tag) {
+            let previous_supply = self.unit_supply(&previous_name);
+            *self.supply.entry(previous_player_id).or_default() -= previous_supply;
+        }
+        let supply = self.unit_supply(unit_name);
+        *self.supply.entry(player_id).or_default() += supply;
+        self.units
+            .insert(unit_tag, (player_id, unit_name.to_string()));
+    }
+
+    /// Adds a step to the build order of a player, the steps made before the game starts, i.e.
+    /// the starting workers, are skipped.
+    fn add_step(&mut self, player_id: u8, item: &str, kind: BuildOrderStepKind, game_loop: i64) {
+        if game_loop == 0 {
+            return;
+        }
+        let supply = self.supply.get(&player_id).copied().unwrap_or_default();
+        let game_secs = self.summary.game_speed.game_loop_secs(game_loop);
+        let player = self.summary.player_by_id(player_id).cloned();
+        self.build_orders
+            .entry(player_id)
+            .or_insert_with(|| BuildOrder {
+                player_id,
+                name: player
+                    .as_ref()
+                    .map(|player| player.name.clone())
+                    .unwrap_or_default(),
+                race: player.map(|player| player.race).unwrap_or_default(),
+                steps: vec![],
+            })
+            .steps
+            .push(BuildOrderStep {
+                supply: supply.round() as u32,
+                game_loop,
+                game_secs,
+                item: item.to_string(),
+                kind,
+            });
+    }
+
+    /// The kind of step of a unit type, from its catalog category. The Protoss warp-ins start
+    /// as a UnitInit like the structures, so the event alone does not tell them apart.
+    fn step_kind(&self, unit_name: &str) -> BuildOrderStepKind {
+        match self.unit_catalog.units.get(unit_name) {
+            Some(entry) if entry.category == UnitCategory::Structure => {
+                BuildOrderStepKind::Structure
+            }
+            _ => BuildOrderStepKind::Unit,
+        }
+    }
+
+    /// Whether a unit type is made by a player, units without a cost, i.e. larva, broodlings or
+    /// MULEs, are not.
+    fn is_buildable(&self, unit_name: &str) -> bool {
+        !IGNORED_UNITS.contains(&unit_name) && self.cost(unit_name) > 0
+    }
+}
+
+impl ReplaySink for BuildOrderExtractor {
+    fn register_summary(&mut self, summary: &ReplaySummary) -> Result<(), SwarmyError> {
+        self.summary = summary.clone();
+        Ok(())
+    }
+
+    fn register_unit_init(
+        &mut self,
+        unit_init: &UnitInitEvent,
+        _change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let player_id = unit_init.control_player_id;
+        if self.is_buildable(&unit_init.unit_type_name) {
+            let kind = self.step_kind(&unit_init.unit_type_name);
+            self.add_step(player_id, &unit_init.unit_type_name, kind, tracker_loop);
+        }
+        let unit_tag = s2protocol::tracker_events::unit_tag(
+            unit_init.unit_tag_index,
+            unit_init.unit_tag_recycle,
+        );
+        self.set_unit(unit_tag, player_id, &unit_init.unit_type_name);
+        Ok(())
+    }
+
+    fn register_unit_born(
+        &mut self,
+        unit_born: &UnitBornEvent,
+        _change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let player_id = unit_born.control_player_id;
+        if self.is_buildable(&unit_born.unit_type_name) {
+            let kind = self.step_kind(&unit_born.unit_type_name);
+            self.add_step(player_id, &unit_born.unit_type_name, kind, tracker_loop);
+        }
+        let unit_tag = s2protocol::tracker_events::unit_tag(
+            unit_born.unit_tag_index,
+            unit_born.unit_tag_recycle,
+        );
+        self.set_unit(unit_tag, player_id, &unit_born.unit_type_name);
+        Ok(())
+    }
+
+    fn register_unit_type_change(
+        &mut self,
+        unit_type_change: &UnitTypeChangeEvent,
+        _change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let unit_tag = s2protocol::tracker_events::unit_tag(
+            unit_type_change.unit_tag_index,
+            unit_type_change.unit_tag_recycle,
+        );
+        let Some((player_id, previous_name)) = self.units.get(&unit_tag).cloned() else {
+            return Ok(());
+        };
+        // Switching modes, i.e. sieging a tank, keeps the cost, only the morphs that cost more
+        // are steps. The cocoons are listed as the unit they morph into.
+        let unit_name = unit_type_change.unit_type_name.as_str();
+        if self.cost(unit_name) > self.cost(&previous_name) {
+            let item = MORPH_COCOONS
+                .iter()
+                .find(|(cocoon, _)| *cocoon == unit_name)
+                .map(|(_, morph)| *morph)
+                .unwrap_or(unit_name);
+            self.add_step(player_id, item, BuildOrderStepKind::Morph, tracker_loop);
+        }
+        self.set_unit(unit_tag, player_id, unit_name);
+        Ok(())
+    }
+
+    fn register_unit_died(
+        &mut self,
+        unit_dead: &UnitDiedEvent,
+        _change_hint: UnitChangeHint,
+        _tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let unit_tag = s2protocol::tracker_events::unit_tag(
+            unit_dead.unit_tag_index,
+            unit_dead.unit_tag_recycle,
+        );
+        if let Some((player_id, unit_name)) = self.units.remove(&unit_tag) {
+            let supply = self.unit_supply(&unit_name);
+            *self.supply.entry(player_id).or_default() -= supply;
+        }
+        Ok(())
+    }
+
+    fn register_upgrade(
+        &mut self,
+        upgrade: &UpgradeEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        if COSMETIC_UPGRADE_PREFIXES
+            .iter()
+            .any(|prefix| upgrade.upgrade_type_name.starts_with(prefix))
+        {
+            return Ok(());
+        }
+        self.add_step(
+            upgrade.player_id,
+            &upgrade.upgrade_type_name,
+            BuildOrderStepKind::Upgrade,
+            tracker_loop,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_lists_the_protoss_warp_ins_as_units() {
+        let mut build_order_extractor = BuildOrderExtractor::new(UnitCatalog::bundled());
+        SC2Rerun::new("assets/2023-04-08-2v2AI.SC2Replay", Default::default())
+            .unwrap()
+            .add_events(&mut build_order_extractor)
+            .unwrap();
+        let build_orders = build_order_extractor.build_orders();
+        let protoss = build_orders
+            .iter()
+            .find(|build_order| build_order.race.starts_with("Prot"))
+            .expect("The replay has a Protoss player");
+        let kind_of = |item: &str| {
+            protoss
+                .steps
+                .iter()
+                .find(|step| step.item == item)
+                .map(|step| step.kind)
+        };
+        assert_eq!(kind_of("Pylon"), Some(BuildOrderStepKind::Structure));
+        assert_eq!(kind_of("Gateway"), Some(BuildOrderStepKind::Structure));
+        assert_eq!(kind_of("Probe"), Some(BuildOrderStepKind::Unit));
+        assert_eq!(kind_of("Stalker"), Some(BuildOrderStepKind::Unit));
+        for step in &protoss.steps {
+            let category = get_unit_category(&step.item, &build_order_extractor.unit_catalog);
+            match step.kind {
+                BuildOrderStepKind::Structure => assert_eq!(category, UnitCategory::Structure),
+                BuildOrderStepKind::Unit => assert_ne!(category, UnitCategory::Structure),
+                _ => {}
+            }
+        }
+    }
+}
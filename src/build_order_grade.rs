@@ -0,0 +1,476 @@
+//! Build order grading, how closely a player followed a reference build order, i.e. the build of
+//! a pro exported with `--build-order-json`.
+//! The steps of the reference are matched in order to the steps of the player with the same item,
+//! so that the seconds they are off are the lowest, the steps are then on time, early, late or
+//! missed. The steps of the player that are not in the reference are extra steps.
+//! The score goes from 0 to 100, a step on time scores 1, a step off by more than the tolerance
+//! scores less the further off it is, and the missed and extra steps score 0.
+
+use super::*;
+use colored::Colorize;
+use std::collections::{BTreeSet, HashMap};
+
+/// How strictly the steps are graded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildOrderGradeConfig {
+    /// The seconds a step can be off from the reference and still be on time.
+    pub tolerance_secs: f32,
+    /// The seconds a step can be off from the reference and still be matched to it.
+    pub max_delta_secs: f32,
+    /// Only the steps of the reference within these seconds are graded, i.e. the opening, all of
+    /// them by default.
+    pub max_secs: Option<f32>,
+}
+
+impl Default for BuildOrderGradeConfig {
+    fn default() -> Self {
+        Self {
+            tolerance_secs: 10.,
+            max_delta_secs: 60.,
+            max_secs: None,
+        }
+    }
+}
+
+/// Aligns the times of the reference steps of an item to the times of the steps of the player, in
+/// order, so that the seconds they are off are the lowest. Leaving a step unmatched costs half the
+/// max delta, so steps further off than that are missed, the steps of the player after the end of
+/// the reference can be left unmatched for free.
+/// Returns the index of the step of the player matched to each reference step.
+fn align(
+    reference_secs: &[f64],
+    actual_secs: &[f64],
+    max_delta_secs: f64,
+    end_secs: f64,
+) -> Vec<Option<usize>> {
+    let skip_cost = max_delta_secs / 2.;
+    let extra_cost = |j: usize| {
+        if actual_secs[j] <= end_secs {
+            skip_cost
+        } else {
+            0.
+        }
+    };
+    let match_cost = |i: usize, j: usize| {
+        let delta_secs = (actual_secs[j] - reference_secs[i]).abs();
+        (delta_secs <= max_delta_secs).then_some(delta_secs)
+    };
+    // The lowest cost of aligning the first i reference steps to the first j steps of the player.
+    let (rows, columns) = (reference_secs.len() + 1, actual_secs.len() + 1);
+    let mut cost = vec![vec![0.; columns]; rows];
+    for i in 1..rows {
+        cost[i][0] = cost[i - 1][0] + skip_cost;
+    }
+    for j in 1..columns {
+        cost[0][j] = cost[0][j - 1] + extra_cost(j - 1);
+    }
+    for i in 1..rows {
+        for j in 1..columns {
+            let mut lowest = (cost[i - 1][j] + skip_cost).min(cost[i][j - 1] + extra_cost(j - 1));
+            if let Some(match_cost) = match_cost(i - 1, j - 1) {
+                lowest = lowest.min(cost[i - 1][j - 1] + match_cost);
+            }
+            cost[i][j] = lowest;
+        }
+    }
+    let mut matches = vec![None; rows - 1];
+    let (mut i, mut j) = (rows - 1, columns - 1);
+    while i > 0 && j > 0 {
+        if match_cost(i - 1, j - 1)
+            .is_some_and(|match_cost| cost[i][j] == cost[i - 1][j - 1] + match_cost)
+        {
+            matches[i - 1] = Some(j - 1);
+            i -= 1;
+            j -= 1;
+        } else if cost[i][j] == cost[i - 1][j] + skip_cost {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+    matches
+}
+
+/// How a step of the reference was followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuildOrderStepStatus {
+    OnTime,
+    Early,
+    Late,
+    Missed,
+}
+
+/// A step of the reference and the step of the player matched to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildOrderStepGrade {
+    pub reference: BuildOrderStep,
+    /// The step of the player, None when missed.
+    pub actual: Option<BuildOrderStep>,
+    pub status: BuildOrderStepStatus,
+    /// The seconds the player was late, negative when early.
+    pub delta_secs: Option<f64>,
+    /// From 0 to 1, how close the step was to the reference.
+    pub score: f64,
+}
+
+/// How closely a player followed a reference build order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildOrderGrade {
+    /// The player that was graded.
+    pub player_id: u8,
+    /// The name of the player the reference was taken from.
+    pub reference_name: String,
+    /// The steps of the reference, in order.
+    pub steps: Vec<BuildOrderStepGrade>,
+    /// The steps of the player within the time of the reference that are not in it.
+    pub extra_steps: Vec<BuildOrderStep>,
+    /// From 0 to 100, how closely the reference was followed.
+    pub score: f64,
+}
+
+impl BuildOrderGrade {
+    /// Matches the steps of a build order to the steps of a reference.
+    pub fn new(reference: &BuildOrder, actual: &BuildOrder, config: BuildOrderGradeConfig) -> Self {
+        let tolerance_secs = config.tolerance_secs as f64;
+        let max_delta_secs = (config.max_delta_secs as f64).max(tolerance_secs);
+        let reference = BuildOrder {
+            steps: reference
+                .steps
+                .iter()
+                .filter(|step| {
+                    config
+                        .max_secs
+                        .is_none_or(|max_secs| step.game_secs <= max_secs as f64)
+                })
+                .cloned()
+                .collect(),
+            ..reference.clone()
+        };
+        // The steps after the reference is over are not extra, the build is done by then.
+        let end_secs = reference
+            .steps
+            .last()
+            .map(|step| step.game_secs + tolerance_secs)
+            .unwrap_or_default();
+        // The steps of each item are aligned on their own, the reference step index to the index
+        // of the step of the player matched to it.
+        let mut matches: HashMap<usize, usize> = HashMap::new();
+        let items: BTreeSet<&str> = reference
+            .steps
+            .iter()
+            .map(|step| step.item.as_str())
+            .collect();
+        for item in items {
+            let reference_idxs: Vec<usize> = (0..reference.steps.len())
+                .filter(|idx| reference.steps[*idx].item == item)
+                .collect();
+            let actual_idxs: Vec<usize> = (0..actual.steps.len())
+                .filter(|idx| actual.steps[*idx].item == item)
+                .collect();
+            let item_matches = align(
+                &reference_idxs
+                    .iter()
+                    .map(|idx| reference.steps[*idx].game_secs)
+                    .collect::<Vec<f64>>(),
+                &actual_idxs
+                    .iter()
+                    .map(|idx| actual.steps[*idx].game_secs)
+                    .collect::<Vec<f64>>(),
+                max_delta_secs,
+                end_secs,
+            );
+            for (reference_idx, item_match) in reference_idxs.into_iter().zip(item_matches) {
+                if let Some(item_match) = item_match {
+                    matches.insert(reference_idx, actual_idxs[item_match]);
+                }
+            }
+        }
+        let mut is_matched = vec![false; actual.steps.len()];
+        let mut steps = Vec::with_capacity(reference.steps.len());
+        for (reference_idx, reference_step) in reference.steps.iter().enumerate() {
+            let closest = matches.get(&reference_idx).copied();
+            let Some(idx) = closest else {
+                steps.push(BuildOrderStepGrade {
+                    reference: reference_step.clone(),
+                    actual: None,
+                    status: BuildOrderStepStatus::Missed,
+                    delta_secs: None,
+                    score: 0.,
+                });
+                continue;
+            };
+            is_matched[idx] = true;
+            let delta_secs = actual.steps[idx].game_secs - reference_step.game_secs;
+            let (status, score) = if delta_secs.abs() <= tolerance_secs {
+                (BuildOrderStepStatus::OnTime, 1.)
+            } else {
+                let status = if delta_secs > 0. {
+                    BuildOrderStepStatus::Late
+                } else {
+                    BuildOrderStepStatus::Early
+                };
+                let off_secs = delta_secs.abs() - tolerance_secs;
+                let score = (1. - off_secs / (max_delta_secs - tolerance_secs)).max(0.);
+                (status, score)
+            };
+            steps.push(BuildOrderStepGrade {
+                reference: reference_step.clone(),
+                actual: Some(actual.steps[idx].clone()),
+                status,
+                delta_secs: Some(delta_secs),
+                score,
+            });
+        }
+        let extra_steps: Vec<BuildOrderStep> = actual
+            .steps
+            .iter()
+            .zip(is_matched)
+            .filter(|(step, is_matched)| !is_matched && step.game_secs <= end_secs)
+            .map(|(step, _)| step.clone())
+            .collect();
+        let total_steps = steps.len() + extra_steps.len();
+        let score = if total_steps == 0 {
+            100.
+        } else {
+            steps.iter().map(|step| step.score).sum::<f64>() / total_steps as f64 * 100.
+        };
+        Self {
+            player_id: actual.player_id,
+            reference_name: reference.name.clone(),
+            steps,
+            extra_steps,
+            score,
+        }
+    }
+
+    /// The number of steps with each status.
+    pub fn count(&self, status: BuildOrderStepStatus) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.status == status)
+            .count()
+    }
+
+    /// A one line summary of the grade.
+    pub fn summary_line(&self) -> String {
+        format!(
+            "Score: {:.0}/100, {} on time, {} early, {} late, {} missed, {} extra",
+            self.score,
+            self.count(BuildOrderStepStatus::OnTime),
+            self.count(BuildOrderStepStatus::Early),
+            self.count(BuildOrderStepStatus::Late),
+            self.count(BuildOrderStepStatus::Missed),
+            self.extra_steps.len()
+        )
+    }
+
+    /// A markdown table of the steps, to be shown in the viewer.
+    pub fn to_markdown(&self) -> String {
+        let mut res = format!(
+            "# Player {} following {}\n\n{}\n\n",
+            self.player_id,
+            self.reference_name,
+            self.summary_line()
+        );
+        res.push_str("| Item | Reference | Actual | Delta | Status |\n");
+        res.push_str("|---|---|---|---|---|\n");
+        for step in &self.steps {
+            res.push_str(&format!(
+                "| {} | {} @{} | {} | {} | {:?} |\n",
+                step.reference.item,
+                step.reference.game_time(),
+                step.reference.supply,
+                step.actual
+                    .as_ref()
+                    .map(|actual| format!("{} @{}", actual.game_time(), actual.supply))
+                    .unwrap_or_default(),
+                step.delta_secs
+                    .map(|delta_secs| format!("{:+.0}s", delta_secs))
+                    .unwrap_or_default(),
+                step.status
+            ));
+        }
+        if !self.extra_steps.is_empty() {
+            res.push_str("\n## Extra steps\n\n| Item | Actual |\n|---|---|\n");
+            for step in &self.extra_steps {
+                res.push_str(&format!(
+                    "| {} | {} @{} |\n",
+                    step.item,
+                    step.game_time(),
+                    step.supply
+                ));
+            }
+        }
+        res
+    }
+
+    /// A colored report of the steps, to be printed in the terminal.
+    pub fn to_report(&self) -> String {
+        let mut res = format!(
+            "Player {} following {}\n{:>6} {:>4} {:<32} {:>6} {:>5}\n",
+            self.player_id, self.reference_name, "Time", "Sup", "Item", "Actual", "Delta"
+        );
+        for step in &self.steps {
+            let line = format!(
+                "{:>6} {:>4} {:<32} {:>6} {:>5}",
+                step.reference.game_time(),
+                step.reference.supply,
+                step.reference.item,
+                step.actual
+                    .as_ref()
+                    .map(|actual| actual.game_time())
+                    .unwrap_or_else(|| "-".to_string()),
+                step.delta_secs
+                    .map(|delta_secs| format!("{:+.0}s", delta_secs))
+                    .unwrap_or_default(),
+            );
+            let line = match step.status {
+                BuildOrderStepStatus::OnTime => line.green(),
+                BuildOrderStepStatus::Early | BuildOrderStepStatus::Late => line.yellow(),
+                BuildOrderStepStatus::Missed => line.red(),
+            };
+            res.push_str(&format!("{} {:?}\n", line, step.status));
+        }
+        for step in &self.extra_steps {
+            res.push_str(&format!(
+                "{}\n",
+                format!(
+                    "{:>6} {:>4} {:<32} Extra",
+                    step.game_time(),
+                    step.supply,
+                    step.item
+                )
+                .cyan()
+            ));
+        }
+        res.push_str(&self.summary_line());
+        res.push('\n');
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_order(steps: &[(&str, f64)]) -> BuildOrder {
+        BuildOrder {
+            player_id: 1,
+            name: "Reference".to_string(),
+            race: "Prot".to_string(),
+            steps: steps
+                .iter()
+                .map(|(item, game_secs)| BuildOrderStep {
+                    supply: 0,
+                    game_loop: (game_secs * 22.4) as i64,
+                    game_secs: *game_secs,
+                    item: item.to_string(),
+                    kind: BuildOrderStepKind::Structure,
+                })
+                .collect(),
+        }
+    }
+
+    fn grade(reference: &[(&str, f64)], actual: &[(&str, f64)]) -> BuildOrderGrade {
+        BuildOrderGrade::new(
+            &build_order(reference),
+            &build_order(actual),
+            BuildOrderGradeConfig::default(),
+        )
+    }
+
+    #[test]
+    fn it_aligns_the_repeated_steps_in_order() {
+        assert_eq!(
+            align(&[10., 20., 30.], &[21., 31.], 60., 40.),
+            vec![None, Some(0), Some(1)]
+        );
+        assert_eq!(
+            align(&[10., 20.], &[12., 19., 25.], 60., 30.),
+            vec![Some(0), Some(1)]
+        );
+        // Further off than the max delta, the step is not matched.
+        assert_eq!(align(&[10.], &[100.], 60., 20.), vec![None]);
+    }
+
+    #[test]
+    fn it_grades_the_steps_on_time() {
+        let grade = grade(
+            &[("Pylon", 20.), ("Gateway", 60.)],
+            &[("Pylon", 25.), ("Gateway", 52.)],
+        );
+        assert_eq!(grade.count(BuildOrderStepStatus::OnTime), 2);
+        assert_eq!(grade.steps[0].delta_secs, Some(5.));
+        assert_eq!(grade.steps[1].delta_secs, Some(-8.));
+        assert!(grade.extra_steps.is_empty());
+        assert_eq!(grade.score, 100.);
+    }
+
+    #[test]
+    fn it_grades_the_late_and_early_steps() {
+        let grade = grade(
+            &[("Pylon", 20.), ("Gateway", 60.)],
+            &[("Pylon", 50.), ("Gateway", 35.)],
+        );
+        assert_eq!(grade.steps[0].status, BuildOrderStepStatus::Late);
+        assert_eq!(grade.steps[1].status, BuildOrderStepStatus::Early);
+        // 20 seconds past the tolerance out of the 50 seconds between it and the max delta.
+        assert!((grade.steps[0].score - 0.6).abs() < 1e-9);
+        assert!((grade.steps[1].score - 0.7).abs() < 1e-9);
+        assert!((grade.score - 65.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_grades_the_missed_steps() {
+        let grade = grade(&[("Pylon", 20.), ("Forge", 60.)], &[("Pylon", 20.)]);
+        assert_eq!(grade.steps[1].status, BuildOrderStepStatus::Missed);
+        assert_eq!(grade.steps[1].actual, None);
+        assert_eq!(grade.steps[1].score, 0.);
+        assert_eq!(grade.score, 50.);
+    }
+
+    #[test]
+    fn it_grades_the_extra_steps() {
+        // The Stargate is not in the reference, the Nexus is after the reference is over.
+        let grade = grade(
+            &[("Pylon", 20.), ("Gateway", 60.)],
+            &[
+                ("Pylon", 20.),
+                ("Stargate", 40.),
+                ("Gateway", 60.),
+                ("Nexus", 90.),
+            ],
+        );
+        assert_eq!(grade.count(BuildOrderStepStatus::OnTime), 2);
+        assert_eq!(grade.extra_steps.len(), 1);
+        assert_eq!(grade.extra_steps[0].item, "Stargate");
+        assert!((grade.score - 200. / 3.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_grades_a_step_too_far_off_as_missed_and_extra() {
+        let grade = grade(&[("Pylon", 20.), ("Gateway", 120.)], &[("Pylon", 100.)]);
+        assert_eq!(grade.steps[0].status, BuildOrderStepStatus::Missed);
+        assert_eq!(grade.extra_steps.len(), 1);
+        assert_eq!(grade.score, 0.);
+    }
+
+    #[test]
+    fn it_errors_when_there_is_no_player_to_grade() {
+        let sc2_rerun =
+            SC2Rerun::new("assets/2023-04-08-2v2AI.SC2Replay", Default::default()).unwrap();
+        let reference = BuildOrder {
+            race: "Unknown".to_string(),
+            ..build_order(&[("Pylon", 20.)])
+        };
+        let config = BuildOrderGradeConfig::default();
+        assert!(matches!(
+            sc2_rerun.grade_build_order(&reference, None, config),
+            Err(SwarmyError::GradePlayerNotFound(_))
+        ));
+        assert!(matches!(
+            sc2_rerun.grade_build_order(&reference, Some(42), config),
+            Err(SwarmyError::GradePlayerNotFound(_))
+        ));
+    }
+}
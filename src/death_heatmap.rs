@@ -0,0 +1,156 @@
+//! Death heatmap, a density grid of where the units have died.
+//! The map is split in square cells, there is a grid per player and per category of the killed
+//! units so that the fights can be told apart from, i.e. workers being picked off.
+
+use super::*;
+use std::collections::{HashMap, VecDeque};
+
+/// The deaths of a player units of a category.
+#[derive(Debug, Default, Clone)]
+struct DeathGrid {
+    /// The number of deaths in each cell.
+    counts: Vec<u32>,
+    /// The game loop and cell of the deaths that are still counted.
+    deaths: VecDeque<(i64, usize)>,
+}
+
+/// The death density grids, by the killed unit owner and category.
+#[derive(Debug, Clone)]
+pub struct DeathHeatmap {
+    /// The size of a side of a cell in map units.
+    cell_size: f32,
+    /// The number of cells horizontally.
+    columns: usize,
+    /// The number of cells vertically.
+    rows: usize,
+    /// Deaths older than this many game loops are not counted, all of them are when missing.
+    window_loops: Option<i64>,
+    /// The grids by player id and unit category.
+    grids: HashMap<(u8, UnitCategory), DeathGrid>,
+}
+
+impl Default for DeathHeatmap {
+    fn default() -> Self {
+        Self::new((256, 256), 4., None)
+    }
+}
+
+impl DeathHeatmap {
+    pub fn new(map_size: (u32, u32), cell_size: f32, window_loops: Option<i64>) -> Self {
+        let cell_size = cell_size.max(1.);
+        Self {
+            cell_size,
+            columns: ((map_size.0 as f32 / cell_size).ceil() as usize).max(1),
+            rows: ((map_size.1 as f32 / cell_size).ceil() as usize).max(1),
+            window_loops,
+            grids: HashMap::new(),
+        }
+    }
+
+    /// The width and height of the grids in cells.
+    pub fn resolution(&self) -> [u32; 2] {
+        [self.columns as u32, self.rows as u32]
+    }
+
+    /// Adds a death to the grid of its player and category, returns the grid that changed.
+    pub fn add_death(
+        &mut self,
+        player_id: u8,
+        category: UnitCategory,
+        pos: (f32, f32),
+        game_loop: i64,
+    ) -> (u8, UnitCategory) {
+        let column = ((pos.0 / self.cell_size).max(0.) as usize).min(self.columns - 1);
+        let row = ((pos.1 / self.cell_size).max(0.) as usize).min(self.rows - 1);
+        let cell = row * self.columns + column;
+        let cells = self.columns * self.rows;
+        let grid = self
+            .grids
+            .entry((player_id, category))
+            .or_insert_with(|| DeathGrid {
+                counts: vec![0; cells],
+                deaths: VecDeque::new(),
+            });
+        grid.counts[cell] += 1;
+        grid.deaths.push_back((game_loop, cell));
+        (player_id, category)
+    }
+
+    /// Removes the deaths that went out of the window at the game loop.
+    /// Returns the grids that changed.
+    pub fn expire_deaths(&mut self, game_loop: i64) -> Vec<(u8, UnitCategory)> {
+        let Some(window_loops) = self.window_loops else {
+            return vec![];
+        };
+        let mut res = vec![];
+        for (key, grid) in self.grids.iter_mut() {
+            let mut expired = false;
+            while grid
+                .deaths
+                .front()
+                .is_some_and(|(death_loop, _)| *death_loop < game_loop - window_loops)
+            {
+                let (_, cell) = grid.deaths.pop_front().expect("The front was checked");
+                grid.counts[cell] -= 1;
+                expired = true;
+            }
+            if expired {
+                res.push(*key);
+            }
+        }
+        res
+    }
+
+    /// Returns the RGBA pixels of a grid, the more deaths in a cell the more opaque its color.
+    /// The rows are flipped so that the north of the map is at the top of the image.
+    pub fn to_rgba(&self, key: (u8, UnitCategory), color: [u8; 4]) -> Option<Vec<u8>> {
+        let grid = self.grids.get(&key)?;
+        let max_count = grid.counts.iter().copied().max().unwrap_or_default().max(1);
+        let mut res = Vec::with_capacity(grid.counts.len() * 4);
+        for row in grid.counts.chunks(self.columns).rev() {
+            for count in row {
+                let alpha = (*count as f32 / max_count as f32 * 255.) as u8;
+                res.extend_from_slice(&[color[0], color[1], color[2], alpha]);
+            }
+        }
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_expires_deaths_out_of_the_window() {
+        let mut heatmap = DeathHeatmap::new((16, 16), 4., Some(100));
+        let army = (1, UnitCategory::Army);
+        let worker = (2, UnitCategory::Worker);
+        assert_eq!(heatmap.add_death(1, UnitCategory::Army, (1., 1.), 0), army);
+        assert_eq!(heatmap.add_death(1, UnitCategory::Army, (1., 1.), 50), army);
+        assert_eq!(
+            heatmap.add_death(2, UnitCategory::Worker, (9., 9.), 80),
+            worker
+        );
+        assert!(heatmap.expire_deaths(100).is_empty());
+        // Only the first army death is out of the window.
+        assert_eq!(heatmap.expire_deaths(101), vec![army]);
+        assert_eq!(heatmap.grids[&army].counts[0], 1);
+        assert!(heatmap.expire_deaths(150).is_empty());
+        let changed = heatmap.expire_deaths(200);
+        assert_eq!(changed.len(), 2);
+        assert!(changed.contains(&army) && changed.contains(&worker));
+        assert!(heatmap.grids[&army].counts.iter().all(|count| *count == 0));
+        // The empty grids are fully transparent.
+        let pixels = heatmap.to_rgba(worker, [255, 0, 0, 255]).unwrap();
+        assert!(pixels.chunks(4).all(|pixel| pixel[3] == 0));
+    }
+
+    #[test]
+    fn it_keeps_all_deaths_without_a_window() {
+        let mut heatmap = DeathHeatmap::new((16, 16), 4., None);
+        heatmap.add_death(1, UnitCategory::Army, (1., 1.), 0);
+        assert!(heatmap.expire_deaths(1_000_000).is_empty());
+        assert_eq!(heatmap.grids[&(1, UnitCategory::Army)].counts[0], 1);
+    }
+}
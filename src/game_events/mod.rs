@@ -5,27 +5,42 @@ use s2protocol::game_events::*;
 use s2protocol::state::SC2UnitCmdData;
 use s2protocol::UnitChangeHint;
 
+/// Draws the area the player is looking at, this is also where the player is paying attention
+/// to in the vision map.
 pub fn register_camera_update(
     user_id: i64,
     camera_update: &CameraUpdateEvent,
-    recording_stream: &RecordingStream,
+    rerun_sink: &mut RerunSink,
     game_loop: i64,
 ) -> Result<(), SwarmyError> {
-    if let Some(target) = &camera_update.m_target {
-        recording_stream.log(
-            format!("Player/{}/Cam", user_id),
-            &rerun::Boxes3D::from_centers_and_half_sizes(
-                [(
-                    target.x as f32 / 250f32,
-                    1. * target.y as f32 / 250f32,
-                    game_loop as f32 / 100.,
-                )],
-                [(5.0, 5.0, 0.025)],
-            )
-            .with_radii([0.025])
-            //.with_labels([user_id.to_string()])
-            .with_colors([user_color(user_id)]),
-        )?;
+    // The cameras of the observers are not drawn.
+    if let (Some(target), Some(player_id)) =
+        (&camera_update.m_target, rerun_sink.user_player_id(user_id))
+    {
+        let entity_path = format!("Player/{}/Cam", player_id);
+        let center = (target.x as f32 / 250f32, 1. * target.y as f32 / 250f32);
+        rerun_sink.vision_map.set_camera(player_id, center);
+        let color = rerun_sink.player_colors.user_color(user_id);
+        if rerun_sink.has_3d() {
+            rerun_sink.recording_stream.log(
+                entity_path.clone(),
+                &rerun::Boxes3D::from_centers_and_half_sizes(
+                    [(center.0, center.1, rerun_sink.z(game_loop))],
+                    [(CAMERA_HALF_SIZE.0, CAMERA_HALF_SIZE.1, 0.025)],
+                )
+                .with_radii([0.025])
+                //.with_labels([user_id.to_string()])
+                .with_colors([color]),
+            )?;
+        }
+        if rerun_sink.has_2d() {
+            rerun_sink.recording_stream.log(
+                format!("Map2D/{}", entity_path),
+                &rerun::Boxes2D::from_centers_and_half_sizes([center], [CAMERA_HALF_SIZE])
+                    .with_radii([0.025])
+                    .with_colors([color]),
+            )?;
+        }
     }
     Ok(())
 }
@@ -33,10 +48,10 @@ pub fn register_camera_update(
 pub fn register_camera_save(
     user_id: i64,
     camera_save: &CameraSaveEvent,
-    recording_stream: &RecordingStream,
+    rerun_sink: &RerunSink,
     game_loop: i64,
 ) -> Result<(), SwarmyError> {
-    recording_stream.log(
+    rerun_sink.recording_stream.log(
         format!("CamSave/{}/{}", user_id, camera_save.m_which),
         &rerun::TextLog::new(format!(
             "{}:{:?}",
@@ -44,20 +59,36 @@ pub fn register_camera_save(
         ))
         .with_level(rerun::TextLogLevel::TRACE),
     )?;
-    recording_stream.log(
-        format!("Player/{}/CamSave/{}", user_id, camera_save.m_which),
-        &rerun::Ellipsoids3D::from_centers_and_half_sizes(
-            [(
-                camera_save.m_target.x as f32 / 250f32,
-                camera_save.m_target.y as f32 / 250f32,
-                game_loop as f32 / 100.,
-            )],
-            [(0.25, 0.25, 0.25)],
-        )
-        .with_line_radii([0.025])
-        .with_labels([format!("{}", camera_save.m_which)])
-        .with_colors([user_color(user_id)]),
-    )?;
+    let Some(player_id) = rerun_sink.user_player_id(user_id) else {
+        return Ok(());
+    };
+    let entity_path = format!("Player/{}/CamSave/{}", player_id, camera_save.m_which);
+    let center = (
+        camera_save.m_target.x as f32 / 250f32,
+        camera_save.m_target.y as f32 / 250f32,
+    );
+    let color = rerun_sink.player_colors.user_color(user_id);
+    if rerun_sink.has_3d() {
+        rerun_sink.recording_stream.log(
+            entity_path.clone(),
+            &rerun::Ellipsoids3D::from_centers_and_half_sizes(
+                [(center.0, center.1, rerun_sink.z(game_loop))],
+                [(0.25, 0.25, 0.25)],
+            )
+            .with_line_radii([0.025])
+            .with_labels([format!("{}", camera_save.m_which)])
+            .with_colors([color]),
+        )?;
+    }
+    if rerun_sink.has_2d() {
+        rerun_sink.recording_stream.log(
+            format!("Map2D/{}", entity_path),
+            &rerun::Points2D::new([center])
+                .with_radii([0.25])
+                .with_labels([format!("{}", camera_save.m_which)])
+                .with_colors([color]),
+        )?;
+    }
     Ok(())
 }
 
@@ -66,26 +97,35 @@ pub fn register_update_target_point(
     user_id: i64,
     change_hint: UnitChangeHint,
     _target_point: &GameSMapCoord3D,
-    recording_stream: &RecordingStream,
+    rerun_sink: &mut RerunSink,
     game_loop: i64,
 ) -> Result<(), SwarmyError> {
     if let UnitChangeHint::TargetPoints(updated_units) = change_hint {
         for selected_unit in updated_units {
+            if rerun_sink.is_unit_dead(&selected_unit) {
+                continue;
+            }
             if let SC2UnitCmdData::TargetPoint(target_point) = &selected_unit.cmd.data {
+                rerun_sink.unit_target_points.insert(
+                    rerun_sink.unit_tag(&selected_unit),
+                    (game_loop, (target_point.x(), target_point.y())),
+                );
                 let unit_target_pos = rerun::Vec3D::new(
                     target_point.x() - selected_unit.pos.x(),
-                    -1. * (target_point.y() + selected_unit.pos.y()),
+                    -(target_point.y() + selected_unit.pos.y()),
                     0.,
                 );
                 let selected_unit_pos = rerun::Vec3D::new(
                     selected_unit.pos.x(),
                     selected_unit.pos.y(),
-                    game_loop as f32 / 100.,
+                    rerun_sink.z(game_loop),
                 );
-                recording_stream.log(
+                rerun_sink.recording_stream.log(
                     format!(
                         "Log/{}/{}/{}/TP",
-                        user_id, selected_unit.name, selected_unit.tag_index
+                        user_id,
+                        selected_unit.name,
+                        rerun_sink.unit_tag(&selected_unit)
                     ),
                     &rerun::TextLog::new(format!(
                         "TP{:?}:{:?}",
@@ -93,11 +133,12 @@ pub fn register_update_target_point(
                     ))
                     .with_level(rerun::TextLogLevel::TRACE),
                 )?;
-                recording_stream.log(
-                    format!("Unit/{}/{}/TP", selected_unit.name, selected_unit.tag_index),
-                    &rerun::Arrows3D::from_vectors([unit_target_pos])
-                        .with_origins([selected_unit_pos])
-                        .with_colors([user_color(user_id)]),
+                rerun_sink.log_arrow(
+                    &rerun_sink.unit_entity_path(&selected_unit, "TP"),
+                    (selected_unit.pos.x(), selected_unit.pos.y()),
+                    (unit_target_pos.x(), unit_target_pos.y()),
+                    game_loop,
+                    rerun_sink.player_colors.user_color(user_id),
                 )?;
             }
         }
@@ -109,7 +150,7 @@ pub fn register_update_target_unit(
     user_id: i64,
     change_hint: UnitChangeHint,
     _target_unit: &GameSCmdDataTargetUnit,
-    recording_stream: &RecordingStream,
+    rerun_sink: &RerunSink,
     game_loop: i64,
 ) -> Result<(), SwarmyError> {
     if let UnitChangeHint::TargetUnits {
@@ -118,21 +159,26 @@ pub fn register_update_target_unit(
     } = change_hint
     {
         for selected_unit in user_selected_units {
+            if rerun_sink.is_unit_dead(&selected_unit) {
+                continue;
+            }
             if let SC2UnitCmdData::TargetUnit(target_unit_data) = &selected_unit.cmd.data {
                 let unit_target_pos = rerun::Vec3D::new(
                     target_unit_data.snapshot_point.x() - selected_unit.pos.x(),
-                    -1. * (target_unit_data.snapshot_point.y() + selected_unit.pos.y()),
+                    -(target_unit_data.snapshot_point.y() + selected_unit.pos.y()),
                     0.,
                 );
                 let selected_unit_pos = rerun::Vec3D::new(
                     selected_unit.pos.x(),
                     selected_unit.pos.y(),
-                    game_loop as f32 / 100.,
+                    rerun_sink.z(game_loop),
                 );
-                recording_stream.log(
+                rerun_sink.recording_stream.log(
                     format!(
                         "Log/{}/{}/{}/TU",
-                        user_id, selected_unit.name, selected_unit.tag_index
+                        user_id,
+                        selected_unit.name,
+                        rerun_sink.unit_tag(&selected_unit)
                     ),
                     &rerun::TextLog::new(format!(
                         "{}({:?})->{}({:?})",
@@ -140,11 +186,12 @@ pub fn register_update_target_unit(
                     ))
                     .with_level(rerun::TextLogLevel::TRACE),
                 )?;
-                recording_stream.log(
-                    format!("Unit/{}/{}/TU", selected_unit.name, selected_unit.tag_index),
-                    &rerun::Arrows3D::from_vectors([unit_target_pos])
-                        .with_origins([selected_unit_pos])
-                        .with_colors([FREYA_RED]),
+                rerun_sink.log_arrow(
+                    &rerun_sink.unit_entity_path(&selected_unit, "TU"),
+                    (selected_unit.pos.x(), selected_unit.pos.y()),
+                    (unit_target_pos.x(), unit_target_pos.y()),
+                    game_loop,
+                    FREYA_RED,
                 )?;
             }
         }
@@ -153,7 +200,7 @@ pub fn register_update_target_unit(
 }
 
 /// Registers units as being selected.
-/// The radius is adjusted on s2protocol side.
+/// The radius is doubled for selected units, see `RerunSink::unit_sized_color`.
 /// The event could be for a non-selected group, for example, a unit in a group may have died
 /// and that would trigger a selection delta. Same if a unit as Larva is part of a group and
 /// then it is born into another unit which triggers a selection delta.
@@ -161,18 +208,25 @@ pub fn register_update_target_unit(
 /// array of selceted units which seems to match the blizzard UI so far.
 pub fn register_selection_delta(
     change_hint: UnitChangeHint,
-    recording_stream: &RecordingStream,
+    rerun_sink: &RerunSink,
     game_loop: i64,
 ) -> Result<(), SwarmyError> {
     if let UnitChangeHint::Selection(changed_units) = change_hint {
         for unit in changed_units {
+            if rerun_sink.is_unit_dead(&unit) {
+                // The dead units are removed from the selection, their entities are cleared.
+                continue;
+            }
             // XXX: Technically this is not "Born", we should have a State or Status that
             // contains the radius of the unit.
-            recording_stream.log(
-                format!("Unit/{}/{}/Born", unit.name, unit.tag_index),
-                &rerun::Points3D::new([(unit.pos.x(), unit.pos.y(), game_loop as f32 / 100.)])
-                    //.with_draw_order(game_loop as f32)
-                    .with_radii([unit.radius]),
+            let (unit_radius, _unit_color) = rerun_sink.unit_sized_color(&unit);
+            rerun_sink.log_point(
+                &rerun_sink.unit_entity_path(&unit, "Born"),
+                (unit.pos.x(), unit.pos.y()),
+                game_loop,
+                unit_radius,
+                None,
+                Some(&unit.name),
             )?;
         }
     }
@@ -184,17 +238,23 @@ pub fn register_selection_delta(
 pub fn update_control_group(
     change_hint: UnitChangeHint,
     ctrl_group_evt: &GameSControlGroupUpdateEvent,
-    recording_stream: &RecordingStream,
+    rerun_sink: &RerunSink,
     game_loop: i64,
 ) -> Result<(), SwarmyError> {
     if let UnitChangeHint::Selection(changed_units) = change_hint {
         if ctrl_group_evt.m_control_group_update == GameEControlGroupUpdate::ERecall {
             for unit in changed_units {
-                recording_stream.log(
-                    format!("Unit/{}/{}/Born", unit.name, unit.tag_index),
-                    &rerun::Points3D::new([(unit.pos.x(), unit.pos.y(), game_loop as f32 / 100.)])
-                        //.with_draw_order(game_loop as f32)
-                        .with_radii([unit.radius]),
+                if rerun_sink.is_unit_dead(&unit) {
+                    continue;
+                }
+                let (unit_radius, _unit_color) = rerun_sink.unit_sized_color(&unit);
+                rerun_sink.log_point(
+                    &rerun_sink.unit_entity_path(&unit, "Born"),
+                    (unit.pos.x(), unit.pos.y()),
+                    game_loop,
+                    unit_radius,
+                    None,
+                    Some(&unit.name),
                 )?;
             }
         }
@@ -206,16 +266,21 @@ pub fn register_cmd(
     user_id: i64,
     change_hint: UnitChangeHint,
     game_cmd: &GameSCmdEvent,
-    recording_stream: &RecordingStream,
+    rerun_sink: &mut RerunSink,
     game_loop: i64,
 ) -> Result<(), SwarmyError> {
+    if let (Some(abil), Some(player_id)) = (&game_cmd.m_abil, rerun_sink.user_player_id(user_id)) {
+        rerun_sink
+            .upgrade_timeline
+            .add_cmd(player_id, &abil.ability, game_loop);
+    }
     match &game_cmd.m_data {
         GameSCmdData::TargetPoint(target) => {
             register_update_target_point(
                 user_id,
                 change_hint.clone(),
                 target,
-                recording_stream,
+                rerun_sink,
                 game_loop,
             )?;
         }
@@ -224,7 +289,7 @@ pub fn register_cmd(
                 user_id,
                 change_hint.clone(),
                 target_unit,
-                recording_stream,
+                rerun_sink,
                 game_loop,
             )?;
         }
@@ -247,8 +312,13 @@ pub fn register_cmd(
             } else {
                 "".to_string()
             };
-            recording_stream.log(
-                format!("Tgt/{}/{}/{}", user_id, unit.name, unit.tag_index),
+            rerun_sink.recording_stream.log(
+                format!(
+                    "Tgt/{}/{}/{}",
+                    user_id,
+                    unit.name,
+                    rerun_sink.unit_tag(unit)
+                ),
                 &rerun::TextLog::new(abil_str).with_level(rerun::TextLogLevel::TRACE),
             )?;
         }
@@ -260,10 +330,10 @@ pub fn handle_chat_message(
     user_id: i64,
     _change_hint: UnitChangeHint,
     chat_message: &GameSTriggerChatMessageEvent,
-    recording_stream: &RecordingStream,
+    rerun_sink: &RerunSink,
     _game_loop: i64,
 ) -> Result<(), SwarmyError> {
-    recording_stream.log(
+    rerun_sink.recording_stream.log(
         format!("Chat/{}", user_id),
         &rerun::TextLog::new(chat_message.m_chat_message.to_string())
             .with_level(rerun::TextLogLevel::TRACE),
@@ -271,56 +341,100 @@ pub fn handle_chat_message(
     Ok(())
 }
 
-/// Registers the game events to Rerun.
+/// Logs the static styling of the activity series, a line in the color of each player named
+/// after them, i.e. `Activity/APM/1`
+pub fn register_activity_series(rerun_sink: &RerunSink) -> Result<(), SwarmyError> {
+    for player in &rerun_sink.summary.players {
+        let color = rerun_sink
+            .player_colors
+            .player_color(player.player_id as i64);
+        for series_name in ["APM", "EPM"] {
+            rerun_sink.recording_stream.log_static(
+                format!("Activity/{}/{}", series_name, player.player_id),
+                &rerun::SeriesLines::new()
+                    .with_colors([color])
+                    .with_names([player.name.clone()]),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Logs a sample of the rolling APM and EPM of each player.
+pub fn register_activity(rerun_sink: &mut RerunSink) -> Result<(), SwarmyError> {
+    for (player_id, apm, epm) in rerun_sink.activity_tracker.rolling(rerun_sink.game_loop) {
+        rerun_sink.recording_stream.log(
+            format!("Activity/APM/{}", player_id),
+            &rerun::Scalars::new([apm]),
+        )?;
+        rerun_sink.recording_stream.log(
+            format!("Activity/EPM/{}", player_id),
+            &rerun::Scalars::new([epm]),
+        )?;
+    }
+    Ok(())
+}
+
+/// Logs the activity of each player over the whole game.
+pub fn register_activity_summary(rerun_sink: &RerunSink) -> Result<(), SwarmyError> {
+    for activity in rerun_sink.activity_tracker.summaries() {
+        tracing::info!(
+            "Player {} APM: {:.0}, EPM: {:.0}",
+            activity.player_id,
+            activity.apm,
+            activity.epm
+        );
+    }
+    rerun_sink.recording_stream.log_static(
+        "Activity/Summary",
+        &rerun::TextDocument::from_markdown(rerun_sink.activity_tracker.to_markdown()),
+    )?;
+    Ok(())
+}
+
+/// Dispatches the game events to the ReplaySink.
 pub fn add_game_event(
     user_id: i64,
     evt: &ReplayGameEvent,
     change_hint: UnitChangeHint,
-    recording_stream: &RecordingStream,
+    sink: &mut dyn ReplaySink,
     game_loop: i64,
 ) -> Result<(), SwarmyError> {
+    sink.register_game_event(user_id, evt, game_loop)?;
     match &evt {
         ReplayGameEvent::CameraSave(camera_save) => {
-            register_camera_save(user_id, camera_save, recording_stream, game_loop)?;
+            sink.register_camera_save(user_id, camera_save, game_loop)?;
         }
         ReplayGameEvent::CameraUpdate(camera_update) => {
-            register_camera_update(user_id, camera_update, recording_stream, game_loop)?;
+            sink.register_camera_update(user_id, camera_update, game_loop)?;
         }
         ReplayGameEvent::Cmd(game_cmd) => {
-            register_cmd(user_id, change_hint, game_cmd, recording_stream, game_loop)?;
+            sink.register_cmd(user_id, change_hint, game_cmd, game_loop)?;
         }
         ReplayGameEvent::CmdUpdateTargetPoint(target_point) => {
-            register_update_target_point(
+            sink.register_update_target_point(
                 user_id,
                 change_hint,
                 &target_point.m_target,
-                recording_stream,
                 game_loop,
             )?;
         }
         ReplayGameEvent::CmdUpdateTargetUnit(target_unit) => {
-            register_update_target_unit(
+            sink.register_update_target_unit(
                 user_id,
                 change_hint,
                 &target_unit.m_target,
-                recording_stream,
                 game_loop,
             )?;
         }
         ReplayGameEvent::ControlGroupUpdate(ctrl_group) => {
-            update_control_group(change_hint, ctrl_group, recording_stream, game_loop)?;
+            sink.update_control_group(change_hint, ctrl_group, game_loop)?;
         }
         ReplayGameEvent::SelectionDelta(_selection_delta) => {
-            register_selection_delta(change_hint, recording_stream, game_loop)?;
+            sink.register_selection_delta(change_hint, game_loop)?;
         }
         ReplayGameEvent::TriggerChatMessage(chat_message) => {
-            handle_chat_message(
-                user_id,
-                change_hint,
-                chat_message,
-                recording_stream,
-                game_loop,
-            )?;
+            sink.handle_chat_message(user_id, change_hint, chat_message, game_loop)?;
         }
         ReplayGameEvent::DropUser(_) => {}
         ReplayGameEvent::SelectionSyncCheck(_) => {}
@@ -0,0 +1,395 @@
+//! Look-ahead hotspots, the places where something important is about to happen.
+//! Since this is a replay, it can be scanned once before it is drawn so that a caster is warned
+//! in advance where to move the camera to, similar to the warnings of a Nydus or a Nuke.
+//! The hotspots are large battles, the deaths of expensive units and structures, and drops:
+//! transports unloading, Nydus Worms and Warp Prisms switching to phasing mode.
+//! The unload commands can't be relied on, the ability names are not always decoded right, so
+//! the drops are found from the tracker positions instead. The units inside a transport are
+//! reported at the position of the transport, once they are reported elsewhere they have been
+//! dropped.
+
+use super::*;
+use s2protocol::SC2Unit;
+use std::collections::HashMap;
+
+/// The units that can drop other units on the map.
+const TRANSPORTS: [&str; 4] = [
+    "Medivac",
+    "WarpPrism",
+    "WarpPrismPhasing",
+    "OverlordTransport",
+];
+
+/// The army units that fly, these can't be carried by the transports.
+const FLYING_UNITS: [&str; 23] = [
+    "Banshee",
+    "Battlecruiser",
+    "BroodLord",
+    "Carrier",
+    "Corruptor",
+    "Interceptor",
+    "Liberator",
+    "LiberatorAG",
+    "Mutalisk",
+    "Observer",
+    "ObserverSiegeMode",
+    "Oracle",
+    "Overlord",
+    "Overseer",
+    "OverseerSiegeMode",
+    "Phoenix",
+    "Raven",
+    "Tempest",
+    "VikingFighter",
+    "Viper",
+    "VoidRay",
+    "Mothership",
+    "LocustMPFlying",
+];
+
+/// Units closer than this to a transport of their owner, in map units, are inside of it.
+const LOADED_RADIUS: f32 = 1.;
+
+/// The last position sample of a transport.
+#[derive(Debug, Clone)]
+struct TransportSample {
+    name: String,
+    user_id: Option<u8>,
+    game_loop: i64,
+    pos: (f32, f32),
+}
+
+/// A unit that has been seen inside of a transport.
+#[derive(Debug, Clone)]
+struct LoadedUnit {
+    /// The tag index of the transport.
+    transport_tag_index: u32,
+    /// The game loop the unit was last seen inside of the transport.
+    game_loop: i64,
+}
+
+/// What is going to happen at a hotspot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HotspotKind {
+    Battle,
+    Death,
+    Drop,
+}
+
+/// What is considered important enough to be a hotspot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HotspotConfig {
+    /// The minimum number of deaths for a battle to be a hotspot.
+    pub min_battle_deaths: u32,
+    /// The minimum minerals and gas spent on a unit for its death to be a hotspot.
+    pub min_death_cost: u32,
+    /// Hotspots of the same kind closer than this, in map units, are merged.
+    pub merge_radius: f32,
+    /// Hotspots of the same kind within these seconds of each other are merged.
+    pub merge_secs: f32,
+}
+
+impl Default for HotspotConfig {
+    fn default() -> Self {
+        Self {
+            min_battle_deaths: 10,
+            min_death_cost: 300,
+            merge_radius: 10.,
+            merge_secs: 10.,
+        }
+    }
+}
+
+/// A place where something important happens at a game loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotspot {
+    pub kind: HotspotKind,
+    /// The game loop the hotspot starts at.
+    pub game_loop: i64,
+    /// The map position of the hotspot.
+    pub pos: (f32, f32),
+    /// The size of the area of the hotspot, in map units.
+    pub radius: f32,
+    /// A short description, i.e. `P2 Medivac drop`
+    pub label: String,
+}
+
+/// Collects the hotspots of a replay as it is stepped through.
+#[derive(Debug, Clone)]
+pub struct HotspotScanner {
+    /// What is considered important enough to be a hotspot.
+    pub config: HotspotConfig,
+    /// The unit catalog, for the cost of the dead units.
+    unit_catalog: UnitCatalog,
+    /// The game speed, to turn the merge seconds into game loops.
+    game_speed: GameSpeed,
+    /// Clusters the deaths into battles.
+    battle_detector: BattleDetector,
+    /// The last position sample of the transports, by tag index.
+    transports: HashMap<u32, TransportSample>,
+    /// The units that have been seen inside of a transport, by tag index.
+    loaded_units: HashMap<u32, LoadedUnit>,
+    /// The hotspots found so far.
+    hotspots: Vec<Hotspot>,
+}
+
+impl HotspotScanner {
+    pub fn new(unit_catalog: UnitCatalog) -> Self {
+        Self {
+            config: HotspotConfig::default(),
+            battle_detector: BattleDetector::new(unit_catalog.clone()),
+            unit_catalog,
+            game_speed: GameSpeed::default(),
+            transports: HashMap::new(),
+            loaded_units: HashMap::new(),
+            hotspots: vec![],
+        }
+    }
+
+    /// Sets what is considered important enough to be a hotspot.
+    pub fn with_config(mut self, config: HotspotConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The hotspots found, sorted by game loop once the scan is finished.
+    pub fn hotspots(&self) -> &[Hotspot] {
+        &self.hotspots
+    }
+
+    /// Adds a hotspot unless there is a recent one of the same kind nearby.
+    fn add_hotspot(&mut self, hotspot: Hotspot) {
+        let merge_loops =
+            (self.config.merge_secs as f64 * self.game_speed.game_loops_per_second()) as i64;
+        let merge_radius = self.config.merge_radius;
+        let is_merged = self
+            .hotspots
+            .iter()
+            .rev()
+            .take_while(|other| hotspot.game_loop - other.game_loop <= merge_loops)
+            .any(|other| {
+                other.kind == hotspot.kind
+                    && (other.pos.0 - hotspot.pos.0).powi(2) + (other.pos.1 - hotspot.pos.1).powi(2)
+                        <= merge_radius.powi(2)
+            });
+        if !is_merged {
+            self.hotspots.push(hotspot);
+        }
+    }
+
+    /// Adds a drop hotspot for a unit.
+    fn add_drop(&mut self, unit: &SC2Unit, pos: (f32, f32), game_loop: i64, label: &str) {
+        self.add_hotspot(Hotspot {
+            kind: HotspotKind::Drop,
+            game_loop,
+            pos,
+            radius: 5.,
+            label: format!("P{} {}", unit.user_id.unwrap_or(99u8), label),
+        });
+    }
+
+    /// Returns the tag index of the transport of the same owner the unit is sampled inside of.
+    fn transport_at(&self, unit: &SC2Unit, game_loop: i64) -> Option<u32> {
+        let pos = (unit.pos.x(), unit.pos.y());
+        self.transports
+            .iter()
+            .find(|(_, transport)| {
+                transport.user_id == unit.user_id
+                    && transport.game_loop == game_loop
+                    && (transport.pos.0 - pos.0).powi(2) + (transport.pos.1 - pos.1).powi(2)
+                        <= LOADED_RADIUS.powi(2)
+            })
+            .map(|(tag_index, _)| *tag_index)
+    }
+
+    /// Whether the unit can be carried by a transport, the ground army units and the workers.
+    fn is_carriable(&self, unit: &SC2Unit) -> bool {
+        !FLYING_UNITS.contains(&unit.name.as_str())
+            && !TRANSPORTS.contains(&unit.name.as_str())
+            && self
+                .unit_catalog
+                .units
+                .get(&unit.name)
+                .is_some_and(|entry| {
+                    matches!(entry.category, UnitCategory::Army | UnitCategory::Worker)
+                })
+    }
+
+    /// Tracks the units carried by the transports, a drop is added when a carried unit is
+    /// sampled away from its transport. The drop happened between the last sample inside the
+    /// transport and this one, the hotspot is at the former so that the warning is not late.
+    fn add_positions(&mut self, units: &[SC2Unit], game_loop: i64) {
+        for unit in units {
+            if TRANSPORTS.contains(&unit.name.as_str()) {
+                self.transports.insert(
+                    unit.tag_index,
+                    TransportSample {
+                        name: unit.name.clone(),
+                        user_id: unit.user_id,
+                        game_loop,
+                        pos: (unit.pos.x(), unit.pos.y()),
+                    },
+                );
+            }
+        }
+        for unit in units {
+            if !self.is_carriable(unit) {
+                continue;
+            }
+            if let Some(transport_tag_index) = self.transport_at(unit, game_loop) {
+                self.loaded_units.insert(
+                    unit.tag_index,
+                    LoadedUnit {
+                        transport_tag_index,
+                        game_loop,
+                    },
+                );
+            } else if let Some(loaded_unit) = self.loaded_units.remove(&unit.tag_index) {
+                let transport_name = self
+                    .transports
+                    .get(&loaded_unit.transport_tag_index)
+                    .map(|transport| transport.name.clone())
+                    .unwrap_or_default();
+                let pos = (unit.pos.x(), unit.pos.y());
+                self.add_drop(
+                    unit,
+                    pos,
+                    loaded_unit.game_loop,
+                    &format!("{} drop", transport_name),
+                );
+            }
+        }
+    }
+}
+
+impl ReplaySink for HotspotScanner {
+    fn register_summary(&mut self, summary: &ReplaySummary) -> Result<(), SwarmyError> {
+        self.game_speed = summary.game_speed;
+        self.battle_detector.register_summary(summary)
+    }
+
+    fn register_unit_init(
+        &mut self,
+        unit_init: &UnitInitEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        if let UnitChangeHint::Registered { unit, .. } = change_hint {
+            if unit.name == "NydusCanal" {
+                let pos = (unit_init.x as f32, unit_init.y as f32);
+                self.add_drop(&unit, pos, tracker_loop, "Nydus Worm");
+            }
+        }
+        Ok(())
+    }
+
+    fn register_unit_type_change(
+        &mut self,
+        _unit_type_change: &UnitTypeChangeEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        if let UnitChangeHint::Registered { unit, .. } = change_hint {
+            if unit.name == "WarpPrismPhasing" {
+                let pos = (unit.pos.x(), unit.pos.y());
+                self.add_drop(&unit, pos, tracker_loop, "Warp Prism warp-in");
+            }
+        }
+        Ok(())
+    }
+
+    fn register_unit_died(
+        &mut self,
+        unit_dead: &UnitDiedEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        if let UnitChangeHint::Unregistered { killed, .. } = change_hint {
+            self.transports.remove(&killed.tag_index);
+            self.loaded_units.remove(&killed.tag_index);
+            self.battle_detector
+                .add_death(&killed, unit_dead, tracker_loop);
+            let Some(entry) = self.unit_catalog.units.get(&killed.name) else {
+                return Ok(());
+            };
+            if unit_dead.killer_player_id.is_none()
+                || matches!(
+                    entry.category,
+                    UnitCategory::Resource | UnitCategory::Neutral
+                )
+                || entry.minerals + entry.gas < self.config.min_death_cost
+            {
+                return Ok(());
+            }
+            self.add_hotspot(Hotspot {
+                kind: HotspotKind::Death,
+                game_loop: tracker_loop,
+                pos: (unit_dead.x as f32, unit_dead.y as f32),
+                radius: entry.radius.max(2.),
+                label: format!("P{} {} dies", killed.user_id.unwrap_or(99u8), killed.name),
+            });
+        }
+        Ok(())
+    }
+
+    fn register_unit_position(
+        &mut self,
+        change_hint: UnitChangeHint,
+        _unit_pos: UnitPositionsEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        if let UnitChangeHint::Positions(units) = change_hint {
+            self.add_positions(&units, tracker_loop);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SwarmyError> {
+        self.battle_detector.close_battles(i64::MAX);
+        for battle in self.battle_detector.battles() {
+            if battle.deaths() < self.config.min_battle_deaths {
+                continue;
+            }
+            let half_size = battle.half_size();
+            self.hotspots.push(Hotspot {
+                kind: HotspotKind::Battle,
+                game_loop: battle.start_loop,
+                pos: battle.center(),
+                radius: half_size.0.max(half_size.1) + 1.,
+                label: format!("Battle: {} deaths", battle.deaths()),
+            });
+        }
+        self.hotspots.sort_by_key(|hotspot| hotspot.game_loop);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drops(file_path: &str) -> Vec<Hotspot> {
+        SC2Rerun::new(file_path, SC2ReplayFilters::default())
+            .unwrap()
+            .scan_hotspots()
+            .unwrap()
+            .into_iter()
+            .filter(|hotspot| hotspot.kind == HotspotKind::Drop)
+            .collect()
+    }
+
+    #[test]
+    fn it_finds_drops_from_the_unit_positions() {
+        // The Ghosts are carried by a Medivac and dropped before the next position sample.
+        let drops = drops("assets/2023-04-08-2v2AI.SC2Replay");
+        assert_eq!(drops.len(), 1);
+        assert_eq!(drops[0].label, "P1 Medivac drop");
+        assert_eq!(drops[0].game_loop, 16226);
+        assert_eq!(drops[0].pos, (33., 144.));
+    }
+
+    #[test]
+    fn it_finds_no_drops_without_transports() {
+        assert!(drops("assets/Burrow.SC2Replay").is_empty());
+    }
+}
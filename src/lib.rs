@@ -7,14 +7,50 @@ use rerun::web_viewer::WebViewerSinkError;
 use std::path::PathBuf;
 // use rerun::external::re_viewer::external::eframe::Error as eframe_Error;
 use rerun::{RecordingStream, RecordingStreamBuilder};
+use s2protocol::game_events::{
+    CameraSaveEvent, CameraUpdateEvent, GameSCmdDataTargetUnit, GameSCmdEvent,
+    GameSControlGroupUpdateEvent, GameSMapCoord3D, GameSTriggerChatMessageEvent, ReplayGameEvent,
+};
 use s2protocol::state::SC2EventIterator;
-use s2protocol::{S2ProtocolError, SC2EventType, SC2ReplayFilters};
+use s2protocol::tracker_events::{
+    PlayerStatsEvent, UnitBornEvent, UnitDiedEvent, UnitDoneEvent, UnitInitEvent,
+    UnitPositionsEvent, UnitTypeChangeEvent, UpgradeEvent,
+};
+use s2protocol::{S2ProtocolError, SC2EventType, SC2ReplayFilters, UnitChangeHint};
 pub use tracker_events::*;
+pub mod activity;
+pub use activity::*;
+pub mod unit_lifecycle;
+pub use unit_lifecycle::*;
 pub mod unit_colors;
 pub use unit_colors::*;
+pub mod battles;
+pub use battles::*;
+pub mod build_order;
+pub use build_order::*;
+pub mod build_order_grade;
+pub use build_order_grade::*;
+pub mod death_heatmap;
+pub use death_heatmap::*;
 pub mod game_events;
 pub use game_events::*;
+pub mod hotspots;
+pub use hotspots::*;
+pub mod production;
+pub use production::*;
+pub mod render_config;
+pub use render_config::*;
+pub mod replay_summary;
+pub use replay_summary::*;
+pub mod rerun_sink;
+pub use rerun_sink::*;
 pub mod tracker_events;
+pub mod unit_catalog;
+pub use unit_catalog::*;
+pub mod upgrades;
+pub use upgrades::*;
+pub mod vision;
+pub use vision::*;
 
 // Some colors I really liked from a Freya Holmer presentation:
 // https://www.youtube.com/watch?v=kfM-yu0iQBk
@@ -36,10 +72,6 @@ pub const FREYA_YELLOW: [u8; 4] = [0xf7, 0xd4, 0x54, 0xff];
 pub const FREYA_LIGHT_YELLOW: [u8; 4] = [0xea, 0xd8, 0xad, 0xff];
 pub const FREYA_LIGHT_GREEN: [u8; 4] = [0x6e, 0xc2, 0x9c, 0xff];
 
-// This was observed in a game with max game_loop = 13735 and a duration of 15:42 = 942 seconds.
-// nanos 942000000000 / 13735 game_loops = 68583909 nanoseconds per game_loop
-pub const GAME_LOOP_SPEED_NANOS: i64 = 68_583_909;
-
 #[derive(thiserror::Error, Debug)]
 pub enum SwarmyError {
     #[error("Rerun Message Sender error")]
@@ -58,6 +90,189 @@ pub enum SwarmyError {
     AddrParse(#[from] std::net::AddrParseError),
     #[error("I/O Error")]
     Io(#[from] std::io::Error),
+    #[error("JSON Error")]
+    Json(#[from] serde_json::Error),
+    #[error("Ctrl-C handler Error")]
+    CtrlC(#[from] ctrlc::Error),
+    #[error("No player to grade: {0}")]
+    GradePlayerNotFound(String),
+}
+
+/// A destination for the replay events as the SC2EventIterator steps through them.
+/// `add_tracker_event` and `add_game_event` dispatch each event to the matching method.
+/// Every method defaults to doing nothing so that a sink only handles the events it needs.
+#[allow(unused_variables)]
+pub trait ReplaySink {
+    /// Called once before any event with the replay metadata.
+    fn register_summary(&mut self, summary: &ReplaySummary) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    /// Sets the time of the events that follow, the event_index is the ordinal of the event in
+    /// the replay so that events within the same game loop keep their order.
+    fn set_time(&mut self, event_index: usize, game_loop: i64) {}
+
+    fn register_unit_init(
+        &mut self,
+        unit_init: &UnitInitEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    /// Called when a unit that was initialized is done, i.e. a structure finished building.
+    fn register_unit_done(
+        &mut self,
+        unit_done: &UnitDoneEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_unit_born(
+        &mut self,
+        unit_born: &UnitBornEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_unit_type_change(
+        &mut self,
+        unit_type_change: &UnitTypeChangeEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_unit_died(
+        &mut self,
+        unit_dead: &UnitDiedEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_unit_position(
+        &mut self,
+        change_hint: UnitChangeHint,
+        unit_pos: UnitPositionsEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_player_stats(
+        &mut self,
+        player_stats: &PlayerStatsEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_upgrade(
+        &mut self,
+        upgrade: &UpgradeEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    /// Called for every game event before it is dispatched to its own method, i.e. to measure
+    /// the activity of the players.
+    fn register_game_event(
+        &mut self,
+        user_id: i64,
+        game_event: &ReplayGameEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_camera_save(
+        &mut self,
+        user_id: i64,
+        camera_save: &CameraSaveEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_camera_update(
+        &mut self,
+        user_id: i64,
+        camera_update: &CameraUpdateEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_cmd(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        game_cmd: &GameSCmdEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_update_target_point(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        target_point: &GameSMapCoord3D,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_update_target_unit(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        target_unit: &GameSCmdDataTargetUnit,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn register_selection_delta(
+        &mut self,
+        change_hint: UnitChangeHint,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn update_control_group(
+        &mut self,
+        change_hint: UnitChangeHint,
+        ctrl_group_evt: &GameSControlGroupUpdateEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    fn handle_chat_message(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        chat_message: &GameSTriggerChatMessageEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        Ok(())
+    }
+
+    /// Called once all the events have been dispatched.
+    fn finish(&mut self) -> Result<(), SwarmyError> {
+        Ok(())
+    }
 }
 
 pub struct SC2Rerun {
@@ -66,50 +281,187 @@ pub struct SC2Rerun {
 
     /// The file path containing the SC2 Replay
     pub file_path: String,
+
+    /// The players, map, duration and result of the replay.
+    pub summary: ReplaySummary,
+
+    /// The sizes and colors of the units.
+    pub unit_catalog: UnitCatalog,
+
+    /// The views and Z axis layout the units are drawn with.
+    pub render_config: RenderConfig,
+
+    /// The filters the replay events are read with, kept for the hotspots pre-scan.
+    pub filters: SC2ReplayFilters,
+
+    /// How closely a player followed a reference build order, drawn on the timeline.
+    pub build_order_grade: Option<BuildOrderGrade>,
 }
 
 impl SC2Rerun {
     pub fn new(file_path: &str, filters: SC2ReplayFilters) -> Result<Self, SwarmyError> {
         let sc2_iterator = s2protocol::state::SC2EventIterator::new(&PathBuf::from(file_path))?
-            .with_filters(filters);
+            .with_filters(filters.clone());
+        let summary = ReplaySummary::new(file_path)?;
         Ok(Self {
             sc2_iterator,
             file_path: file_path.to_string(),
+            summary,
+            unit_catalog: UnitCatalog::bundled(),
+            render_config: RenderConfig::default(),
+            filters,
+            build_order_grade: None,
         })
     }
 
-    pub fn add_events(self, recording_stream: &RecordingStream) -> Result<(), SwarmyError> {
+    /// Sets the catalog used to size and color the units.
+    pub fn with_unit_catalog(mut self, unit_catalog: UnitCatalog) -> Self {
+        self.unit_catalog = unit_catalog;
+        self
+    }
+
+    /// Sets the views and Z axis layout the units are drawn with.
+    pub fn with_render_config(mut self, render_config: RenderConfig) -> Self {
+        self.render_config = render_config;
+        self
+    }
+
+    /// Sets the grade of a player against a reference build order, drawn on the timeline.
+    pub fn with_build_order_grade(mut self, build_order_grade: BuildOrderGrade) -> Self {
+        self.build_order_grade = Some(build_order_grade);
+        self
+    }
+
+    /// Returns who played, on what map, for how long and who won.
+    pub fn summary(&self) -> &ReplaySummary {
+        &self.summary
+    }
+
+    /// Steps through a copy of the replay to find where the important events happen.
+    pub fn scan_hotspots(&self) -> Result<Vec<Hotspot>, SwarmyError> {
+        let mut hotspot_scanner = HotspotScanner::new(self.unit_catalog.clone());
+        SC2Rerun::new(&self.file_path, self.filters.clone())?
+            .with_unit_catalog(self.unit_catalog.clone())
+            .add_events(&mut hotspot_scanner)?;
+        Ok(hotspot_scanner.hotspots().to_vec())
+    }
+
+    /// Steps through a copy of the replay to extract the build order of each player.
+    pub fn build_orders(&self) -> Result<Vec<BuildOrder>, SwarmyError> {
+        let mut build_order_extractor = BuildOrderExtractor::new(self.unit_catalog.clone());
+        SC2Rerun::new(&self.file_path, self.filters.clone())?
+            .with_unit_catalog(self.unit_catalog.clone())
+            .add_events(&mut build_order_extractor)?;
+        Ok(build_order_extractor.build_orders())
+    }
+
+    /// Grades how closely a player followed a reference build order.
+    /// When no player is given, the first player with the race of the reference is graded.
+    pub fn grade_build_order(
+        &self,
+        reference: &BuildOrder,
+        player_id: Option<u8>,
+        config: BuildOrderGradeConfig,
+    ) -> Result<BuildOrderGrade, SwarmyError> {
+        let player_id = match player_id {
+            Some(player_id) => self
+                .summary
+                .players
+                .iter()
+                .find(|player| player.player_id == player_id)
+                .map(|player| player.player_id)
+                .ok_or_else(|| {
+                    SwarmyError::GradePlayerNotFound(format!(
+                        "there is no player {} in the replay",
+                        player_id
+                    ))
+                })?,
+            None => self
+                .summary
+                .players
+                .iter()
+                .find(|player| player.race == reference.race)
+                .map(|player| player.player_id)
+                .ok_or_else(|| {
+                    SwarmyError::GradePlayerNotFound(format!(
+                        "there is no {} player in the replay, select the player to grade",
+                        reference.race
+                    ))
+                })?,
+        };
+        let actual = self
+            .build_orders()?
+            .into_iter()
+            .find(|build_order| build_order.player_id == player_id)
+            .unwrap_or_else(|| BuildOrder {
+                player_id,
+                ..Default::default()
+            });
+        Ok(BuildOrderGrade::new(reference, &actual, config))
+    }
+
+    /// Creates the sink that draws the events into the recording stream.
+    /// When the render config has a lookahead, the replay is scanned for hotspots first.
+    fn rerun_sink(&self, recording_stream: RecordingStream) -> Result<RerunSink, SwarmyError> {
+        let mut sink = RerunSink::new(recording_stream)
+            .with_unit_catalog(self.unit_catalog.clone())
+            .with_render_config(self.render_config);
+        if self.render_config.lookahead_secs.is_some() {
+            sink = sink.with_hotspots(self.scan_hotspots()?);
+        }
+        if let Some(build_order_grade) = &self.build_order_grade {
+            sink = sink.with_build_order_grade(build_order_grade.clone());
+        }
+        Ok(sink)
+    }
+
+    /// Steps through the replay and dispatches every event to the sink.
+    /// Returns the number of events that were dispatched.
+    pub fn add_events(self, sink: &mut dyn ReplaySink) -> Result<usize, SwarmyError> {
+        let mut total_events = 0usize;
+        sink.register_summary(&self.summary)?;
         for (event, change_hint) in self.sc2_iterator {
             match event {
                 SC2EventType::Tracker {
                     tracker_loop,
                     event,
                 } => {
-                    recording_stream.set_time_sequence("log", tracker_loop);
-                    add_tracker_event(&event, change_hint, recording_stream, tracker_loop)?
+                    sink.set_time(total_events, tracker_loop);
+                    add_tracker_event(&event, change_hint, sink, tracker_loop)?
                 }
                 SC2EventType::Game {
                     game_loop,
                     user_id,
                     event,
                 } => {
-                    recording_stream.set_time_sequence("log", game_loop);
-                    add_game_event(user_id, &event, change_hint, recording_stream, game_loop)?
+                    sink.set_time(total_events, game_loop);
+                    add_game_event(user_id, &event, change_hint, sink, game_loop)?
                 }
             }
+            total_events += 1;
         }
-        std::thread::sleep(std::time::Duration::from_secs(100000));
-        Ok(())
+        sink.finish()?;
+        Ok(total_events)
     }
 
     /// Calls the native viewer to display the recorded data.
-    pub fn show(self) -> Result<(), SwarmyError> {
-        let recording_stream = RecordingStreamBuilder::new(self.file_path.clone()).spawn()?;
-        self.add_events(&recording_stream)
+    /// Blocks until the viewer is closed or Ctrl-C is pressed so that the stream stays open for
+    /// the viewer.
+    pub fn show(self) -> Result<usize, SwarmyError> {
+        let spawn_options = rerun::SpawnOptions::default();
+        let recording_stream = RecordingStreamBuilder::new(self.file_path.clone())
+            .spawn_opts(&spawn_options, rerun::default_flush_timeout())?;
+        let mut sink = self.rerun_sink(recording_stream)?;
+        let total_events = self.add_events(&mut sink)?;
+        wait_for_viewer(Some(spawn_options.connect_addr()))?;
+        sink.recording_stream.disconnect();
+        Ok(total_events)
     }
 
     /// Connects to a remote address and ships the events
-    pub fn connect(self, addr: Option<String>) -> Result<(), SwarmyError> {
+    /// The gRPC server only lives as long as the recording stream, so this blocks until Ctrl-C
+    /// is pressed, the server does not tell when the web viewers disconnect.
+    pub fn connect(self, addr: Option<String>) -> Result<usize, SwarmyError> {
         //let mut endpoint = String::from("127.0.0.1:9876/proxy");
         // We need to find the current epoch in seconds:
         let epoch_seconds = std::time::SystemTime::now()
@@ -129,13 +481,45 @@ impl SC2Rerun {
             SwarmyError::RerunWebViewer(rerun::web_viewer::WebViewerSinkError::WebViewerServer(e))
         })?
         .detach();
-        self.add_events(&recording_stream)
+        let mut sink = self.rerun_sink(recording_stream)?;
+        let total_events = self.add_events(&mut sink)?;
+        wait_for_viewer(None)?;
+        sink.recording_stream.disconnect();
+        Ok(total_events)
     }
 
     /// Saves the recording into an RRD file.
-    pub fn save_to_file(self, output: &str) -> Result<(), SwarmyError> {
+    /// The file sink is flushed and closed before returning.
+    pub fn save_to_file(self, output: &str) -> Result<usize, SwarmyError> {
         let recording_stream = RecordingStreamBuilder::new(self.file_path.clone()).save(output)?;
-        self.add_events(&recording_stream)
+        let mut sink = self.rerun_sink(recording_stream)?;
+        let total_events = self.add_events(&mut sink)?;
+        sink.recording_stream.disconnect();
+        Ok(total_events)
+    }
+}
+
+/// Blocks the current thread until Ctrl-C is received, or until the viewer listening on an
+/// address stops accepting connections, i.e. its window was closed.
+fn wait_for_viewer(viewer_addr: Option<std::net::SocketAddr>) -> Result<(), SwarmyError> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = tx.send(());
+    })?;
+    tracing::info!("All events have been sent, close the viewer or press Ctrl-C to exit");
+    let poll_interval = std::time::Duration::from_secs(1);
+    loop {
+        match rx.recv_timeout(poll_interval) {
+            // A disconnect means the handler was dropped, either way we are shutting down.
+            Ok(()) | Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+        }
+        if let Some(viewer_addr) = viewer_addr {
+            if std::net::TcpStream::connect_timeout(&viewer_addr, poll_interval).is_err() {
+                tracing::info!("The viewer at {} was closed", viewer_addr);
+                return Ok(());
+            }
+        }
     }
 }
 
@@ -14,8 +14,8 @@ struct Cli {
     #[arg(short, long, value_name = "FILE")]
     source: String,
 
-    /// Whether to include the player stats. This should be later move into a filter where specific
-    /// event types can be excluded/included but for now this is just clutter.
+    /// Whether to include the player stats, drawn as time series under `Stats/`. This should be
+    /// later move into a filter where specific event types can be excluded/included.
     #[arg(long, default_value_t = false)]
     include_stats: bool,
 
@@ -61,6 +61,84 @@ struct Cli {
 
     #[arg(long, default_value_t = true)]
     serve_web: bool,
+
+    /// A JSON unit catalog with sizes and colors, its entries override the bundled catalog.
+    #[arg(long, value_name = "FILE")]
+    unit_catalog: Option<String>,
+
+    /// The views to draw the units into, the time-as-Z 3D view, a flat 2D map or both.
+    #[arg(long, value_enum, default_value_t = ViewMode::Time3D)]
+    view_mode: ViewMode,
+
+    /// How the game loop is mapped to the Z axis of the 3D view.
+    #[arg(long, value_enum, default_value_t = ZMapping::Linear)]
+    z_mapping: ZMapping,
+
+    /// The factor applied to the Z mapping, the default draws a game loop every 100th of a unit.
+    #[arg(long, default_value_t = 0.01)]
+    z_scale: f32,
+
+    /// Only draws the last seconds of the unit movement trails, the whole path by default.
+    #[arg(long, value_name = "SECS")]
+    trail_window_secs: Option<f32>,
+
+    /// Estimates the unit positions every this many game loops between the tracker position
+    /// samples, these are drawn translucent under `Estimated`.
+    #[arg(long, value_name = "LOOPS")]
+    interpolation_step_loops: Option<i64>,
+
+    /// Makes the estimated positions go through the points the units were ordered to move to.
+    #[arg(long, default_value_t = false)]
+    interpolate_with_targets: bool,
+
+    /// The size of the side of the death heatmap cells, in map units.
+    #[arg(long, default_value_t = 4.)]
+    heatmap_cell_size: f32,
+
+    /// Only counts the deaths of the last seconds in the heatmap, all of them by default.
+    #[arg(long, value_name = "SECS")]
+    heatmap_window_secs: Option<f32>,
+
+    /// Scans the replay for battles, expensive deaths and drops before drawing it, and draws a
+    /// countdown marker this many seconds ahead of each of them under `Hotspot/`.
+    #[arg(long, value_name = "SECS")]
+    lookahead_secs: Option<f32>,
+
+    /// Draws what each player sees and has scouted under `Vision/`, updated every this many
+    /// seconds.
+    #[arg(long, value_name = "SECS")]
+    vision_step_secs: Option<f32>,
+
+    /// The size of the side of the vision cells, in map units.
+    #[arg(long, default_value_t = 4.)]
+    vision_cell_size: f32,
+
+    /// Writes the build order of each player to a JSON file before drawing the replay.
+    #[arg(long, value_name = "FILE")]
+    build_order_json: Option<String>,
+
+    /// Grades a player against a reference build order JSON file, as written by
+    /// `--build-order-json`. The report is printed and the steps are drawn on the timeline under
+    /// `BuildOrderGrade/`.
+    #[arg(long, value_name = "FILE")]
+    reference_build: Option<String>,
+
+    /// The player of the reference build order file to follow, the first one by default.
+    #[arg(long)]
+    reference_player_id: Option<u8>,
+
+    /// The player to grade, the first player with the race of the reference by default.
+    #[arg(long)]
+    grade_player_id: Option<u8>,
+
+    /// The seconds a step can be off from the reference build order and still be on time.
+    #[arg(long, default_value_t = 10.)]
+    grade_tolerance_secs: f32,
+
+    /// Only grades the steps of the reference build order within these seconds, i.e. the
+    /// opening, all of them by default.
+    #[arg(long, value_name = "SECS")]
+    grade_max_secs: Option<f32>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -94,15 +172,64 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         include_stats: cli.include_stats,
     };
     tracing::error!("Swarmy Filters: {:?}", filters);
-    let sc2_rerun = SC2Rerun::new(&cli.source, filters)?;
-    if let Some(output) = cli.output {
-        sc2_rerun.save_to_file(&output)?;
+    let mut unit_catalog = UnitCatalog::bundled();
+    if let Some(unit_catalog_file) = cli.unit_catalog {
+        unit_catalog.extend(UnitCatalog::from_file(&unit_catalog_file)?);
+    }
+    let mut sc2_rerun = SC2Rerun::new(&cli.source, filters)?
+        .with_unit_catalog(unit_catalog)
+        .with_render_config(
+            RenderConfig::default()
+                .with_view_mode(cli.view_mode)
+                .with_z_mapping(cli.z_mapping)
+                .with_z_scale(cli.z_scale)
+                .with_trail_window_secs(cli.trail_window_secs)
+                .with_interpolation_step_loops(cli.interpolation_step_loops)
+                .with_interpolate_with_targets(cli.interpolate_with_targets)
+                .with_heatmap_cell_size(cli.heatmap_cell_size)
+                .with_heatmap_window_secs(cli.heatmap_window_secs)
+                .with_lookahead_secs(cli.lookahead_secs)
+                .with_vision_step_secs(cli.vision_step_secs)
+                .with_vision_cell_size(cli.vision_cell_size),
+        );
+    tracing::info!("Replay Summary: {:?}", sc2_rerun.summary());
+    if let Some(build_order_json) = cli.build_order_json {
+        let build_orders = sc2_rerun.build_orders()?;
+        std::fs::write(
+            &build_order_json,
+            serde_json::to_string_pretty(&build_orders)?,
+        )?;
+        tracing::info!("Build orders written to {}", build_order_json);
+    }
+    if let Some(reference_build) = cli.reference_build {
+        let reference = BuildOrder::from_file(&reference_build)?
+            .into_iter()
+            .find(|build_order| {
+                cli.reference_player_id
+                    .is_none_or(|player_id| build_order.player_id == player_id)
+            })
+            .ok_or("The reference build order file has no matching player")?;
+        let build_order_grade = sc2_rerun.grade_build_order(
+            &reference,
+            cli.grade_player_id,
+            BuildOrderGradeConfig {
+                tolerance_secs: cli.grade_tolerance_secs,
+                max_secs: cli.grade_max_secs,
+                ..Default::default()
+            },
+        )?;
+        println!("{}", build_order_grade.to_report());
+        sc2_rerun = sc2_rerun.with_build_order_grade(build_order_grade);
+    }
+    let total_events = if let Some(output) = cli.output {
+        sc2_rerun.save_to_file(&output)?
     } else if cli.serve_web {
-        sc2_rerun.connect(None)?;
+        sc2_rerun.connect(None)?
     } else if let Some(addr) = cli.connect {
-        sc2_rerun.connect(Some(addr))?;
+        sc2_rerun.connect(Some(addr))?
     } else {
-        sc2_rerun.show()?;
-    }
+        sc2_rerun.show()?
+    };
+    tracing::info!("Shutting down, {} events logged", total_events);
     Ok(())
 }
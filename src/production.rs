@@ -0,0 +1,715 @@
+//! Production, what each production and research structure was doing over time.
+//! A structure is busy while it makes a unit, morphs or researches, and idle the rest of the
+//! time from when it is done until it dies or the game ends. The busy spans are taken from the
+//! tracker events, each unit born names the structure that made it, so the structure was busy for
+//! the build time of the unit before that, a morph (i.e. an orbital command or a lair) keeps it
+//! busy for the build time of the morph. The units warped in are not born from a structure, each
+//! of them keeps the warp gate of the player that has been free the longest busy for its cooldown.
+//! The commands are only used for the research spans of the upgrades, see UpgradeTimeline, the
+//! ability names are not always decoded with the same protocol as the replay and the commands
+//! that are cancelled or queued without resources never turn into a unit. Each research span
+//! keeps the structure of its type that has been free the longest busy, i.e. one of the forges.
+//! The overlapping spans of a reactor or a queue are merged, so the structure is busy once.
+//! The Zerg units are made from the larva rather than a structure, so the hatcheries are only
+//! busy with the queens, their morphs and their research, this is noted in the summary.
+//!
+//! A player is supply blocked while the supply used reaches the supply made, below 200, this is
+//! taken from the player stats, so it needs `--include-stats`.
+//!
+//! The ProductionTracker is a ReplaySink on its own, so it can be used for post-game reports:
+//! ```no_run
+//! use swarmy::*;
+//! let filters = s2protocol::SC2ReplayFilters {
+//!     include_stats: true,
+//!     ..Default::default()
+//! };
+//! let sc2_rerun = SC2Rerun::new("assets/Burrow.SC2Replay", filters).unwrap();
+//! let mut production_tracker = ProductionTracker::new(UnitCatalog::bundled());
+//! sc2_rerun.add_events(&mut production_tracker).unwrap();
+//! println!("{}", production_tracker.to_markdown());
+//! ```
+
+use super::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// The structures that produce units, these are tracked along the research structures.
+pub const PRODUCTION_STRUCTURES: [&str; 14] = [
+    "Nexus",
+    "Gateway",
+    "WarpGate",
+    "RoboticsFacility",
+    "Stargate",
+    "CommandCenter",
+    "OrbitalCommand",
+    "PlanetaryFortress",
+    "Barracks",
+    "Factory",
+    "Starport",
+    "Hatchery",
+    "Lair",
+    "Hive",
+];
+
+/// The cooldown in seconds at Faster speed of a warp gate after warping in a unit.
+const WARP_GATE_COOLDOWN_SECS: [(&str, f32); 6] = [
+    ("Zealot", 20.),
+    ("Sentry", 23.),
+    ("Stalker", 23.),
+    ("Adept", 20.),
+    ("HighTemplar", 32.),
+    ("DarkTemplar", 32.),
+];
+
+/// When a structure is considered idle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProductionConfig {
+    /// A structure idle for at least these seconds is an idle production period.
+    pub min_idle_secs: f32,
+    /// The busy spans closer than these seconds are merged, i.e. the time it takes to start the
+    /// next unit of a queue.
+    pub merge_gap_secs: f32,
+}
+
+impl Default for ProductionConfig {
+    fn default() -> Self {
+        Self {
+            min_idle_secs: 10.,
+            merge_gap_secs: 1.,
+        }
+    }
+}
+
+/// A span of time a production structure was busy or idle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductionSpan {
+    pub player_id: u8,
+    pub unit_tag: i64,
+    /// The last type of the structure, i.e. `OrbitalCommand`
+    pub structure: String,
+    /// Whether the structure was making something, it was idle otherwise.
+    pub is_busy: bool,
+    pub start_loop: i64,
+    pub end_loop: i64,
+    /// The row of the structure in the timeline.
+    pub lane: usize,
+}
+
+/// A span of time a player could not make units for lack of supply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupplyBlock {
+    pub player_id: u8,
+    /// The supply made the player was blocked at.
+    pub supply: i32,
+    pub start_loop: i64,
+    pub end_loop: i64,
+}
+
+/// The production of a player over the whole game.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProductionSummary {
+    pub player_id: u8,
+    /// The number of production structures the player had.
+    pub structures: usize,
+    /// The seconds of production of all the structures.
+    pub busy_secs: f64,
+    /// The seconds all the structures were idle.
+    pub idle_secs: f64,
+    /// The number of idle production periods.
+    pub idle_periods: usize,
+    /// The number of supply blocks.
+    pub supply_blocks: usize,
+    /// The seconds the player was supply blocked.
+    pub supply_block_secs: f64,
+}
+
+/// A production structure of a player.
+#[derive(Debug, Clone)]
+struct ProductionStructure {
+    player_id: u8,
+    /// The current type of the structure.
+    name: String,
+    /// The game loop the structure was done at.
+    ready_loop: i64,
+    /// The game loop the structure died at.
+    died_loop: Option<i64>,
+    /// The game loop spans the structure was making something, in the order they were added.
+    busy: Vec<(i64, i64)>,
+    /// The row of the structure in the timeline.
+    lane: usize,
+}
+
+/// Tracks the busy and idle spans of the production structures as the replay is stepped through.
+#[derive(Debug, Clone)]
+pub struct ProductionTracker {
+    /// When a structure is considered idle.
+    pub config: ProductionConfig,
+    /// The unit catalog, for the cost and build time of the units.
+    unit_catalog: UnitCatalog,
+    /// The replay metadata, for the game speed.
+    summary: ReplaySummary,
+    /// The production structures that are done, by unit tag.
+    structures: BTreeMap<i64, ProductionStructure>,
+    /// The owner and type of the production structures being built, by unit tag.
+    pending: HashMap<i64, (u8, String)>,
+    /// The supply made and the game loop of the ongoing supply block, by player id.
+    supply_blocked: BTreeMap<u8, (i32, i64)>,
+    /// The supply blocks that are over.
+    supply_blocks: Vec<SupplyBlock>,
+    /// The research spans of the upgrades.
+    upgrade_timeline: UpgradeTimeline,
+    /// The game loop of the last event.
+    game_loop: i64,
+}
+
+impl ProductionTracker {
+    pub fn new(unit_catalog: UnitCatalog) -> Self {
+        Self {
+            config: ProductionConfig::default(),
+            unit_catalog,
+            summary: ReplaySummary::default(),
+            structures: BTreeMap::new(),
+            pending: HashMap::new(),
+            supply_blocked: BTreeMap::new(),
+            supply_blocks: vec![],
+            upgrade_timeline: UpgradeTimeline::default(),
+            game_loop: 0,
+        }
+    }
+
+    /// Sets when a structure is considered idle.
+    pub fn with_config(mut self, config: ProductionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Whether a structure type is tracked, it makes units or researches upgrades.
+    fn is_tracked(structure_name: &str) -> bool {
+        PRODUCTION_STRUCTURES.contains(&structure_name) || is_research_structure(structure_name)
+    }
+
+    /// Returns the game loops in the seconds of the config, at the speed of the replay.
+    fn secs_to_loops(&self, secs: f32) -> i64 {
+        (secs as f64 * self.summary.game_speed.game_loops_per_second()) as i64
+    }
+
+    /// Returns the game loops in the seconds of the game data, these are Faster seconds.
+    fn faster_secs_to_loops(secs: f32) -> i64 {
+        (secs as f64 * FASTER_GAME_LOOPS_PER_SECOND) as i64
+    }
+
+    /// Returns the game loops it takes to make a unit type, None for the units that are not
+    /// made, i.e. MULEs, or unknown types.
+    fn build_loops(&self, unit_name: &str) -> Option<i64> {
+        self.unit_catalog
+            .units
+            .get(unit_name)
+            .filter(|entry| entry.minerals + entry.gas > 0 && entry.build_secs > 0.)
+            .map(|entry| Self::faster_secs_to_loops(entry.build_secs))
+    }
+
+    /// Starts tracking a production structure that is done.
+    fn add_structure(&mut self, unit_tag: i64, player_id: u8, name: &str, ready_loop: i64) {
+        let lane = self.structures.len();
+        self.structures.insert(
+            unit_tag,
+            ProductionStructure {
+                player_id,
+                name: name.to_string(),
+                ready_loop,
+                died_loop: None,
+                busy: vec![],
+                lane,
+            },
+        );
+    }
+
+    /// Marks a structure busy for the game loops it took to make a unit type, up to a game loop.
+    fn add_busy(&mut self, unit_tag: i64, unit_name: &str, end_loop: i64) {
+        let Some(build_loops) = self.build_loops(unit_name) else {
+            return;
+        };
+        if let Some(structure) = self.structures.get_mut(&unit_tag) {
+            let start_loop = (end_loop - build_loops).max(structure.ready_loop);
+            if end_loop > start_loop {
+                structure.busy.push((start_loop, end_loop));
+            }
+        }
+    }
+
+    /// Returns the tag of the alive structure of a player of one of the types that has been free
+    /// the longest, only the ones free at the game loop when given.
+    fn longest_free(&self, player_id: u8, names: &[&str], free_at: Option<i64>) -> Option<i64> {
+        let free_since = |structure: &ProductionStructure| {
+            structure
+                .busy
+                .iter()
+                .map(|(_, busy_end_loop)| *busy_end_loop)
+                .max()
+                .unwrap_or(structure.ready_loop)
+        };
+        self.structures
+            .iter()
+            .filter(|(_, structure)| {
+                structure.player_id == player_id
+                    && names.contains(&structure.name.as_str())
+                    && structure.died_loop.is_none()
+                    && free_at.is_none_or(|free_at| free_since(structure) <= free_at)
+            })
+            .min_by_key(|(_, structure)| free_since(structure))
+            .map(|(unit_tag, _)| *unit_tag)
+    }
+
+    /// Marks the warp gate of a player that has been free the longest busy for the cooldown of a
+    /// unit warped in.
+    fn add_warp_in(&mut self, player_id: u8, unit_name: &str, start_loop: i64) {
+        let Some(cooldown_secs) = WARP_GATE_COOLDOWN_SECS
+            .iter()
+            .find(|(name, _)| *name == unit_name)
+            .map(|(_, secs)| *secs)
+        else {
+            return;
+        };
+        let end_loop = start_loop + Self::faster_secs_to_loops(cooldown_secs);
+        if let Some(unit_tag) = self.longest_free(player_id, &["WarpGate"], Some(start_loop)) {
+            if let Some(structure) = self.structures.get_mut(&unit_tag) {
+                structure.busy.push((start_loop, end_loop));
+            }
+        }
+    }
+
+    /// Marks the research structure of a player that has been free the longest busy for the
+    /// research span of an upgrade, or the one free the soonest when all of them were busy.
+    fn add_research(&mut self, upgrade_span: &UpgradeSpan) {
+        let names = research_structures(&upgrade_span.name);
+        let Some(unit_tag) = self
+            .longest_free(upgrade_span.player_id, names, Some(upgrade_span.start_loop))
+            .or_else(|| self.longest_free(upgrade_span.player_id, names, None))
+        else {
+            return;
+        };
+        if let Some(structure) = self.structures.get_mut(&unit_tag) {
+            let start_loop = upgrade_span.start_loop.max(structure.ready_loop);
+            if upgrade_span.end_loop > start_loop {
+                structure.busy.push((start_loop, upgrade_span.end_loop));
+            }
+        }
+    }
+
+    /// Returns the busy and idle spans of each structure, sorted by structure and game loop.
+    pub fn spans(&self) -> Vec<ProductionSpan> {
+        let merge_gap_loops = self.secs_to_loops(self.config.merge_gap_secs);
+        let mut spans = vec![];
+        for (unit_tag, structure) in &self.structures {
+            let end_loop = structure.died_loop.unwrap_or(self.game_loop);
+            // The cooldown of a warp gate may go past its death or the end of the game.
+            let mut busy: Vec<(i64, i64)> = structure
+                .busy
+                .iter()
+                .filter(|(start_loop, _)| *start_loop < end_loop)
+                .map(|(start_loop, busy_end_loop)| (*start_loop, (*busy_end_loop).min(end_loop)))
+                .collect();
+            busy.sort();
+            let mut merged: Vec<(i64, i64)> = vec![];
+            for (start_loop, busy_end_loop) in busy {
+                match merged.last_mut() {
+                    Some(last) if start_loop <= last.1 + merge_gap_loops => {
+                        last.1 = last.1.max(busy_end_loop);
+                    }
+                    _ => merged.push((start_loop, busy_end_loop)),
+                }
+            }
+            let span = |start_loop: i64, end_loop: i64, is_busy: bool| ProductionSpan {
+                player_id: structure.player_id,
+                unit_tag: *unit_tag,
+                structure: structure.name.clone(),
+                is_busy,
+                start_loop,
+                end_loop,
+                lane: structure.lane,
+            };
+            let mut idle_start_loop = structure.ready_loop;
+            for (start_loop, busy_end_loop) in merged {
+                if start_loop > idle_start_loop {
+                    spans.push(span(idle_start_loop, start_loop, false));
+                }
+                spans.push(span(start_loop, busy_end_loop, true));
+                idle_start_loop = busy_end_loop;
+            }
+            if end_loop > idle_start_loop {
+                spans.push(span(idle_start_loop, end_loop, false));
+            }
+        }
+        spans
+    }
+
+    /// Returns the spans the structures were idle for at least the minimum idle seconds.
+    pub fn idle_periods(&self) -> Vec<ProductionSpan> {
+        let min_idle_loops = self.secs_to_loops(self.config.min_idle_secs);
+        self.spans()
+            .into_iter()
+            .filter(|span| !span.is_busy && span.end_loop - span.start_loop >= min_idle_loops)
+            .collect()
+    }
+
+    /// Returns the supply blocks of the players, the ongoing ones end at the last event.
+    pub fn supply_blocks(&self) -> Vec<SupplyBlock> {
+        let mut supply_blocks = self.supply_blocks.clone();
+        for (player_id, (supply, start_loop)) in &self.supply_blocked {
+            supply_blocks.push(SupplyBlock {
+                player_id: *player_id,
+                supply: *supply,
+                start_loop: *start_loop,
+                end_loop: self.game_loop,
+            });
+        }
+        supply_blocks.sort_by_key(|supply_block| (supply_block.start_loop, supply_block.player_id));
+        supply_blocks
+    }
+
+    /// Returns the number of busy and idle structures of a player each time they change.
+    pub fn player_counts(&self, player_id: u8) -> Vec<(i64, usize, usize)> {
+        let mut changes: BTreeMap<i64, (i64, i64)> = BTreeMap::new();
+        for span in self.spans() {
+            if span.player_id != player_id {
+                continue;
+            }
+            let delta = |change: &mut (i64, i64), value: i64| {
+                if span.is_busy {
+                    change.0 += value;
+                } else {
+                    change.1 += value;
+                }
+            };
+            delta(changes.entry(span.start_loop).or_default(), 1);
+            delta(changes.entry(span.end_loop).or_default(), -1);
+        }
+        let (mut busy, mut idle) = (0i64, 0i64);
+        changes
+            .into_iter()
+            .map(|(game_loop, (busy_delta, idle_delta))| {
+                busy += busy_delta;
+                idle += idle_delta;
+                (game_loop, busy.max(0) as usize, idle.max(0) as usize)
+            })
+            .collect()
+    }
+
+    /// Returns the production of each player over the whole game, sorted by player id.
+    pub fn summaries(&self) -> Vec<ProductionSummary> {
+        let game_speed = self.summary.game_speed;
+        let mut summaries: BTreeMap<u8, ProductionSummary> = BTreeMap::new();
+        fn summary(
+            summaries: &mut BTreeMap<u8, ProductionSummary>,
+            player_id: u8,
+        ) -> &mut ProductionSummary {
+            summaries
+                .entry(player_id)
+                .or_insert_with(|| ProductionSummary {
+                    player_id,
+                    ..Default::default()
+                })
+        }
+        for structure in self.structures.values() {
+            summary(&mut summaries, structure.player_id).structures += 1;
+        }
+        for span in self.spans() {
+            let secs = game_speed.game_loop_secs(span.end_loop - span.start_loop);
+            if span.is_busy {
+                summary(&mut summaries, span.player_id).busy_secs += secs;
+            } else {
+                summary(&mut summaries, span.player_id).idle_secs += secs;
+            }
+        }
+        for span in self.idle_periods() {
+            summary(&mut summaries, span.player_id).idle_periods += 1;
+        }
+        for supply_block in self.supply_blocks() {
+            let player_summary = summary(&mut summaries, supply_block.player_id);
+            player_summary.supply_blocks += 1;
+            player_summary.supply_block_secs +=
+                game_speed.game_loop_secs(supply_block.end_loop - supply_block.start_loop);
+        }
+        summaries.into_values().collect()
+    }
+
+    /// A markdown table of the production of each player, to be shown in the viewer.
+    pub fn to_markdown(&self) -> String {
+        let mut res = String::from("# Production\n\n");
+        res.push_str(
+            "| Player | Structures | Busy | Idle | Idle periods | Supply blocks | Blocked |\n",
+        );
+        res.push_str("|---|---|---|---|---|---|---|\n");
+        for summary in self.summaries() {
+            let name = self
+                .summary
+                .player_by_id(summary.player_id)
+                .map(|player| player.name.clone())
+                .unwrap_or_else(|| summary.player_id.to_string());
+            let total_secs = summary.busy_secs + summary.idle_secs;
+            let busy_pct = if total_secs > 0. {
+                summary.busy_secs / total_secs * 100.
+            } else {
+                0.
+            };
+            res.push_str(&format!(
+                "| {} | {} | {:.0}s ({:.0}%) | {:.0}s | {} | {} | {:.0}s |\n",
+                name,
+                summary.structures,
+                summary.busy_secs,
+                busy_pct,
+                summary.idle_secs,
+                summary.idle_periods,
+                summary.supply_blocks,
+                summary.supply_block_secs
+            ));
+        }
+        if self
+            .summary
+            .players
+            .iter()
+            .any(|player| player.race == "Zerg")
+        {
+            res.push_str(
+                "\nThe Zerg larva production is not tracked, only the queens, morphs and research \
+                 of the hatcheries.\n",
+            );
+        }
+        res
+    }
+}
+
+impl ReplaySink for ProductionTracker {
+    fn register_summary(&mut self, summary: &ReplaySummary) -> Result<(), SwarmyError> {
+        self.summary = summary.clone();
+        self.upgrade_timeline.register_summary(summary)
+    }
+
+    fn set_time(&mut self, _event_index: usize, game_loop: i64) {
+        self.game_loop = game_loop;
+    }
+
+    fn register_unit_init(
+        &mut self,
+        unit_init: &UnitInitEvent,
+        _change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let player_id = unit_init.control_player_id;
+        if Self::is_tracked(&unit_init.unit_type_name) {
+            let unit_tag = s2protocol::tracker_events::unit_tag(
+                unit_init.unit_tag_index,
+                unit_init.unit_tag_recycle,
+            );
+            self.pending
+                .insert(unit_tag, (player_id, unit_init.unit_type_name.clone()));
+        } else {
+            // The units warped in are initialized where they warp in.
+            self.add_warp_in(player_id, &unit_init.unit_type_name, tracker_loop);
+        }
+        Ok(())
+    }
+
+    fn register_unit_done(
+        &mut self,
+        unit_done: &UnitDoneEvent,
+        _change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let unit_tag = s2protocol::tracker_events::unit_tag(
+            unit_done.unit_tag_index,
+            unit_done.unit_tag_recycle,
+        );
+        if let Some((player_id, name)) = self.pending.remove(&unit_tag) {
+            self.add_structure(unit_tag, player_id, &name, tracker_loop);
+        }
+        Ok(())
+    }
+
+    fn register_unit_born(
+        &mut self,
+        unit_born: &UnitBornEvent,
+        _change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        // The starting structures are born rather than built.
+        if Self::is_tracked(&unit_born.unit_type_name) {
+            let unit_tag = s2protocol::tracker_events::unit_tag(
+                unit_born.unit_tag_index,
+                unit_born.unit_tag_recycle,
+            );
+            self.add_structure(
+                unit_tag,
+                unit_born.control_player_id,
+                &unit_born.unit_type_name,
+                tracker_loop,
+            );
+        }
+        if let (Some(creator_tag_index), Some(creator_tag_recycle)) = (
+            unit_born.creator_unit_tag_index,
+            unit_born.creator_unit_tag_recycle,
+        ) {
+            let creator_tag =
+                s2protocol::tracker_events::unit_tag(creator_tag_index, creator_tag_recycle);
+            self.add_busy(creator_tag, &unit_born.unit_type_name, tracker_loop);
+        }
+        Ok(())
+    }
+
+    fn register_unit_type_change(
+        &mut self,
+        unit_type_change: &UnitTypeChangeEvent,
+        _change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let unit_tag = s2protocol::tracker_events::unit_tag(
+            unit_type_change.unit_tag_index,
+            unit_type_change.unit_tag_recycle,
+        );
+        let unit_name = unit_type_change.unit_type_name.as_str();
+        if let Some((_, name)) = self.pending.get_mut(&unit_tag) {
+            *name = unit_name.to_string();
+        }
+        let Some(structure) = self.structures.get_mut(&unit_tag) else {
+            return Ok(());
+        };
+        // Lifting off or switching to a warp gate keeps the cost, only the morphs that cost more,
+        // i.e. an orbital command, keep the structure busy.
+        let previous_name = std::mem::replace(&mut structure.name, unit_name.to_string());
+        let cost = |unit_name: &str| {
+            self.unit_catalog
+                .units
+                .get(unit_name)
+                .map(|entry| entry.minerals + entry.gas)
+                .unwrap_or_default()
+        };
+        if cost(unit_name) > cost(&previous_name) {
+            self.add_busy(unit_tag, unit_name, tracker_loop);
+        }
+        Ok(())
+    }
+
+    fn register_unit_died(
+        &mut self,
+        unit_dead: &UnitDiedEvent,
+        _change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let unit_tag = s2protocol::tracker_events::unit_tag(
+            unit_dead.unit_tag_index,
+            unit_dead.unit_tag_recycle,
+        );
+        self.pending.remove(&unit_tag);
+        if let Some(structure) = self.structures.get_mut(&unit_tag) {
+            structure.died_loop = Some(tracker_loop);
+        }
+        Ok(())
+    }
+
+    fn register_player_stats(
+        &mut self,
+        player_stats: &PlayerStatsEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        let player_id = player_stats.player_id;
+        let stats = &player_stats.stats;
+        let is_blocked =
+            stats.food_made > 0 && stats.food_made < 200 && stats.food_used >= stats.food_made;
+        match (is_blocked, self.supply_blocked.get(&player_id).copied()) {
+            (true, None) => {
+                self.supply_blocked
+                    .insert(player_id, (stats.food_made, tracker_loop));
+            }
+            (false, Some((supply, start_loop))) => {
+                self.supply_blocked.remove(&player_id);
+                self.supply_blocks.push(SupplyBlock {
+                    player_id,
+                    supply,
+                    start_loop,
+                    end_loop: tracker_loop,
+                });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn register_upgrade(
+        &mut self,
+        upgrade: &UpgradeEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        if let Some(upgrade_span) = self.upgrade_timeline.add_upgrade(
+            upgrade.player_id,
+            &upgrade.upgrade_type_name,
+            tracker_loop,
+        ) {
+            self.add_research(&upgrade_span);
+        }
+        Ok(())
+    }
+
+    fn register_cmd(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        game_cmd: &GameSCmdEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.upgrade_timeline
+            .register_cmd(user_id, change_hint, game_cmd, game_loop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn production(file_path: &str) -> ProductionTracker {
+        let mut production_tracker = ProductionTracker::new(UnitCatalog::bundled());
+        SC2Rerun::new(file_path, SC2ReplayFilters::default())
+            .unwrap()
+            .add_events(&mut production_tracker)
+            .unwrap();
+        production_tracker
+    }
+
+    /// Whether a busy span of the player covers the game loops in a structure of the type.
+    fn is_busy(
+        spans: &[ProductionSpan],
+        player_id: u8,
+        structure: &str,
+        loops: (i64, i64),
+    ) -> bool {
+        spans.iter().any(|span| {
+            span.is_busy
+                && span.player_id == player_id
+                && span.structure == structure
+                && span.start_loop <= loops.0
+                && span.end_loop >= loops.1
+        })
+    }
+
+    #[test]
+    fn it_tracks_the_research_structures() {
+        let spans = production("assets/test.SC2Replay").spans();
+        // WarpGateResearch and ProtossShieldsLevel1 of the Protoss player.
+        assert!(is_busy(&spans, 3, "CyberneticsCore", (5143, 7383)));
+        assert!(is_busy(&spans, 3, "Forge", (24985, 27874)));
+        // ZergGroundArmorsLevel1 of a Zerg player.
+        assert!(is_busy(&spans, 2, "EvolutionChamber", (9726, 12279)));
+    }
+
+    #[test]
+    fn it_tracks_the_hatcheries_and_notes_the_larva() {
+        let production_tracker = production("assets/test.SC2Replay");
+        let spans = production_tracker.spans();
+        for player_id in [1, 2] {
+            assert!(spans.iter().any(|span| span.is_busy
+                && span.player_id == player_id
+                && ["Hatchery", "Lair", "Hive"].contains(&span.structure.as_str())));
+        }
+        assert!(production_tracker
+            .to_markdown()
+            .contains("The Zerg larva production is not tracked"));
+    }
+}
@@ -0,0 +1,173 @@
+//! Rendering options, how the replay is laid out in the viewer.
+
+use super::*;
+
+/// The views the units are drawn into.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ViewMode {
+    /// A 3D view where the game loop is used as the Z axis, the events stack as a tower.
+    #[default]
+    #[value(name = "time3d")]
+    Time3D,
+    /// A flat top-down minimap under `Map2D/`, the units move in place over the timeline.
+    #[value(name = "map2d")]
+    Map2D,
+    /// Both the 3D and the 2D views.
+    #[value(name = "both")]
+    Both,
+}
+
+impl ViewMode {
+    /// Whether the time-as-Z 3D entities should be logged.
+    pub fn has_3d(&self) -> bool {
+        matches!(self, ViewMode::Time3D | ViewMode::Both)
+    }
+
+    /// Whether the `Map2D/` entities should be logged.
+    pub fn has_2d(&self) -> bool {
+        matches!(self, ViewMode::Map2D | ViewMode::Both)
+    }
+}
+
+/// How the game loop of an event is turned into the Z coordinate of the 3D view.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ZMapping {
+    /// The game loop multiplied by the Z scale.
+    #[default]
+    Linear,
+    /// The in-game seconds multiplied by the Z scale.
+    Seconds,
+    /// The natural log of the game loop multiplied by the Z scale, the late game is compressed.
+    Log,
+    /// Everything is drawn at Z 0.
+    Flat,
+}
+
+/// The layout of the drawn entities, shared by the tracker and game event renderers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderConfig {
+    /// The views the units are drawn into.
+    pub view_mode: ViewMode,
+    /// How the game loop is mapped to the Z axis.
+    pub z_mapping: ZMapping,
+    /// The factor applied to the Z mapping.
+    pub z_scale: f32,
+    /// Only the last seconds of the unit trails are drawn, the whole path is drawn when missing.
+    pub trail_window_secs: Option<f32>,
+    /// Estimates the unit positions every this many game loops between the tracker position
+    /// samples, nothing is estimated when missing.
+    pub interpolation_step_loops: Option<i64>,
+    /// Whether the estimated positions go through the point the unit was ordered to move to.
+    pub interpolate_with_targets: bool,
+    /// The size of the side of the death heatmap cells, in map units.
+    pub heatmap_cell_size: f32,
+    /// Only the deaths of the last seconds are counted in the heatmap, all of them when missing.
+    pub heatmap_window_secs: Option<f32>,
+    /// The seconds ahead of the hotspots their countdown markers are drawn, no replay pre-scan is
+    /// done when missing.
+    pub lookahead_secs: Option<f32>,
+    /// The size of the side of the vision cells, in map units.
+    pub vision_cell_size: f32,
+    /// How often the vision of the players is drawn, it is not drawn when missing.
+    pub vision_step_secs: Option<f32>,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            view_mode: ViewMode::default(),
+            z_mapping: ZMapping::default(),
+            // A game loop every 100th of a unit, a 20 minutes game is about 270 units tall.
+            z_scale: 0.01,
+            trail_window_secs: None,
+            interpolation_step_loops: None,
+            interpolate_with_targets: false,
+            heatmap_cell_size: 4.,
+            heatmap_window_secs: None,
+            lookahead_secs: None,
+            vision_cell_size: 4.,
+            vision_step_secs: None,
+        }
+    }
+}
+
+impl RenderConfig {
+    /// Sets the views the units are drawn into.
+    pub fn with_view_mode(mut self, view_mode: ViewMode) -> Self {
+        self.view_mode = view_mode;
+        self
+    }
+
+    /// Sets how the game loop is mapped to the Z axis.
+    pub fn with_z_mapping(mut self, z_mapping: ZMapping) -> Self {
+        self.z_mapping = z_mapping;
+        self
+    }
+
+    /// Sets the factor applied to the Z mapping.
+    pub fn with_z_scale(mut self, z_scale: f32) -> Self {
+        self.z_scale = z_scale;
+        self
+    }
+
+    /// Sets the seconds of the unit trails that are drawn.
+    pub fn with_trail_window_secs(mut self, trail_window_secs: Option<f32>) -> Self {
+        self.trail_window_secs = trail_window_secs;
+        self
+    }
+
+    /// Sets how often the unit positions are estimated between the tracker samples.
+    pub fn with_interpolation_step_loops(mut self, interpolation_step_loops: Option<i64>) -> Self {
+        self.interpolation_step_loops = interpolation_step_loops;
+        self
+    }
+
+    /// Sets whether the estimated positions go through the ordered target points.
+    pub fn with_interpolate_with_targets(mut self, interpolate_with_targets: bool) -> Self {
+        self.interpolate_with_targets = interpolate_with_targets;
+        self
+    }
+
+    /// Sets the size of the death heatmap cells.
+    pub fn with_heatmap_cell_size(mut self, heatmap_cell_size: f32) -> Self {
+        self.heatmap_cell_size = heatmap_cell_size;
+        self
+    }
+
+    /// Sets the seconds of deaths counted in the heatmap.
+    pub fn with_heatmap_window_secs(mut self, heatmap_window_secs: Option<f32>) -> Self {
+        self.heatmap_window_secs = heatmap_window_secs;
+        self
+    }
+
+    /// Sets the seconds ahead of the hotspots their countdown markers are drawn.
+    pub fn with_lookahead_secs(mut self, lookahead_secs: Option<f32>) -> Self {
+        self.lookahead_secs = lookahead_secs;
+        self
+    }
+
+    /// Sets the size of the vision cells.
+    pub fn with_vision_cell_size(mut self, vision_cell_size: f32) -> Self {
+        self.vision_cell_size = vision_cell_size;
+        self
+    }
+
+    /// Sets how often the vision of the players is drawn.
+    pub fn with_vision_step_secs(mut self, vision_step_secs: Option<f32>) -> Self {
+        self.vision_step_secs = vision_step_secs;
+        self
+    }
+
+    /// Returns the Z coordinate of an event in the 3D view.
+    /// The game speed is needed to convert the game loop into seconds.
+    pub fn z(&self, game_loop: i64, game_speed: GameSpeed) -> f32 {
+        let game_loop = game_loop.max(0);
+        let z = match self.z_mapping {
+            ZMapping::Linear => game_loop as f64,
+            ZMapping::Seconds => game_speed.game_loop_secs(game_loop),
+            ZMapping::Log => (game_loop as f64).ln_1p(),
+            ZMapping::Flat => 0.,
+        };
+        z as f32 * self.z_scale
+    }
+}
@@ -0,0 +1,211 @@
+//! Replay summary: who played, on what map, for how long and who won.
+//! This is read from the replay header, details and initData sectors of the MPQ file.
+
+use super::*;
+use s2protocol::details::Details;
+use s2protocol::InitData;
+
+/// The game loops in a second at Faster speed. The times of the game data, i.e. the build and
+/// research times, are in Faster seconds whatever the speed the replay was played at.
+pub const FASTER_GAME_LOOPS_PER_SECOND: f64 = 16. * 1.4;
+
+/// The game speed the replay was played at.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GameSpeed {
+    Slower,
+    Slow,
+    Normal,
+    Fast,
+    #[default]
+    Faster,
+}
+
+impl From<u8> for GameSpeed {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => GameSpeed::Slower,
+            1 => GameSpeed::Slow,
+            2 => GameSpeed::Normal,
+            3 => GameSpeed::Fast,
+            _ => GameSpeed::Faster,
+        }
+    }
+}
+
+impl GameSpeed {
+    /// The number of game loops that happen in a real-time second.
+    /// The Normal speed runs at 16 game loops per second, the rest are multipliers of it.
+    pub fn game_loops_per_second(&self) -> f64 {
+        match self {
+            GameSpeed::Slower => 16. * 0.6,
+            GameSpeed::Slow => 16. * 0.8,
+            GameSpeed::Normal => 16.,
+            GameSpeed::Fast => 16. * 1.2,
+            GameSpeed::Faster => FASTER_GAME_LOOPS_PER_SECOND,
+        }
+    }
+
+    /// The real-time seconds elapsed at a game loop.
+    pub fn game_loop_secs(&self, game_loop: i64) -> f64 {
+        game_loop as f64 / self.game_loops_per_second()
+    }
+}
+
+/// The outcome of the game for a player.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerResult {
+    #[default]
+    Unknown,
+    Victory,
+    Defeat,
+    Tie,
+}
+
+impl From<u8> for PlayerResult {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => PlayerResult::Victory,
+            2 => PlayerResult::Defeat,
+            3 => PlayerResult::Tie,
+            _ => PlayerResult::Unknown,
+        }
+    }
+}
+
+/// A player in the replay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerSummary {
+    /// The player id as used by the tracker events, this is 1-based.
+    pub player_id: u8,
+    /// The user id as used by the game events, from the lobby slots.
+    pub user_id: Option<i64>,
+    /// The player name, without the clan tag.
+    pub name: String,
+    /// The race the player played as.
+    pub race: String,
+    /// The team the player belongs to.
+    pub team_id: u8,
+    /// The in-game color of the player.
+    pub color: [u8; 4],
+    /// Whether the player won or lost.
+    pub result: PlayerResult,
+}
+
+/// The metadata of a replay.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplaySummary {
+    /// The map title.
+    pub map_title: String,
+    /// The players that took part in the game.
+    pub players: Vec<PlayerSummary>,
+    /// The game speed.
+    pub game_speed: GameSpeed,
+    /// The total number of game loops in the replay.
+    pub elapsed_game_loops: u32,
+    /// The width and height of the map.
+    pub map_size: (u32, u32),
+}
+
+impl ReplaySummary {
+    /// Reads the summary from the replay file.
+    pub fn new(file_path: &str) -> Result<Self, SwarmyError> {
+        let (mpq, file_contents) = s2protocol::read_mpq(file_path)?;
+        let (_tail, header) = s2protocol::read_protocol_header(&mpq)?;
+        let details = Details::new(file_path, &mpq, &file_contents)?;
+        let init_data = InitData::new(file_path, &mpq, &file_contents)?;
+        let lobby_slots = &init_data.sync_lobby_state.lobby_state.slots;
+        let players = details
+            .player_list
+            .iter()
+            .filter_map(|player| {
+                // The working_set_slot_id is 0-based while the tracker player_id is 1-based.
+                let working_set_slot_id = player.working_set_slot_id?;
+                let user_id = lobby_slots
+                    .iter()
+                    .find(|slot| slot.working_set_slot_id == Some(working_set_slot_id))
+                    .and_then(|slot| slot.user_id);
+                // The name may be prepend by its clan, like "&lt;CLAN&gt;<sp/>PLAYERNAME"
+                let name = player.name.split("<sp/>").last().unwrap_or_default();
+                Some(PlayerSummary {
+                    player_id: working_set_slot_id + 1,
+                    user_id,
+                    name: name.to_string(),
+                    race: player.race.clone(),
+                    team_id: player.team_id,
+                    color: [player.color.r, player.color.g, player.color.b, 0xff],
+                    result: PlayerResult::from(player.result),
+                })
+            })
+            .collect();
+        let game_description = &init_data.sync_lobby_state.game_description;
+        Ok(Self {
+            map_title: details.title,
+            players,
+            game_speed: GameSpeed::from(details.game_speed),
+            elapsed_game_loops: header.m_elapsed_game_loops,
+            map_size: (
+                game_description.map_size_x as u32,
+                game_description.map_size_y as u32,
+            ),
+        })
+    }
+
+    /// The real-time duration of the game.
+    pub fn duration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(
+            self.game_speed
+                .game_loop_secs(self.elapsed_game_loops as i64),
+        )
+    }
+
+    /// The players that won the game, empty if the result is not known.
+    pub fn winners(&self) -> Vec<&PlayerSummary> {
+        self.players
+            .iter()
+            .filter(|player| player.result == PlayerResult::Victory)
+            .collect()
+    }
+
+    /// Finds a player by the tracker events player id.
+    pub fn player_by_id(&self, player_id: u8) -> Option<&PlayerSummary> {
+        self.players
+            .iter()
+            .find(|player| player.player_id == player_id)
+    }
+
+    /// Finds a player by the game events user id.
+    pub fn player_by_user_id(&self, user_id: i64) -> Option<&PlayerSummary> {
+        self.players
+            .iter()
+            .find(|player| player.user_id == Some(user_id))
+    }
+
+    /// Returns the tracker player id of a game events user id.
+    /// None when the user is not a player, i.e. an observer.
+    pub fn user_player_id(&self, user_id: i64) -> Option<u8> {
+        self.player_by_user_id(user_id)
+            .map(|player| player.player_id)
+    }
+
+    /// A markdown representation of the summary, to be shown in the viewer.
+    pub fn to_markdown(&self) -> String {
+        let duration_secs = self.duration().as_secs();
+        let mut res = format!(
+            "# {}\n\nDuration: {:02}:{:02} ({} game loops at {:?} speed)\n\n",
+            self.map_title,
+            duration_secs / 60,
+            duration_secs % 60,
+            self.elapsed_game_loops,
+            self.game_speed,
+        );
+        res.push_str("| Player | Name | Race | Team | Result |\n");
+        res.push_str("|---|---|---|---|---|\n");
+        for player in &self.players {
+            res.push_str(&format!(
+                "| {} | {} | {} | {} | {:?} |\n",
+                player.player_id, player.name, player.race, player.team_id, player.result
+            ));
+        }
+        res
+    }
+}
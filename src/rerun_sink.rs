@@ -0,0 +1,755 @@
+//! Rerun implementation of the ReplaySink.
+
+use super::*;
+use s2protocol::SC2Unit;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The map positions a unit has been seen at, with the game loop they were sampled at.
+pub type UnitTrail = VecDeque<(i64, (f32, f32))>;
+
+/// Draws the replay events into a Rerun RecordingStream.
+pub struct RerunSink {
+    /// The stream to log the drawn entities to.
+    pub recording_stream: RecordingStream,
+    /// The replay metadata, set once the replay summary is registered.
+    pub summary: ReplaySummary,
+    /// The in-game player colors, set once the replay summary is registered.
+    pub player_colors: PlayerColors,
+    /// The sizes and colors of the units.
+    pub unit_catalog: UnitCatalog,
+    /// The views and Z axis layout the units are drawn with.
+    pub render_config: RenderConfig,
+    /// The recycle count of the units by unit tag index, the SC2Unit only carries the
+    /// index so this is tracked from the unit lifecycle events. It is kept after the unit dies
+    /// so that a dead unit, i.e. removed from a selection, still resolves to its own tag.
+    pub unit_tag_recycles: HashMap<u32, u32>,
+    /// The tags of the units that have died, nothing should be logged for them anymore.
+    pub dead_unit_tags: HashSet<i64>,
+    /// The entity paths logged for the alive units, these are cleared when the unit dies.
+    /// The loggers only borrow the sink, so this is kept in a RefCell.
+    pub unit_lifecycle: RefCell<UnitLifecycle>,
+    /// The current type of the alive units, by unit tag.
+    pub unit_types: HashMap<i64, String>,
+    /// The movement trails of the units, by unit tag.
+    pub unit_trails: HashMap<i64, UnitTrail>,
+    /// Where the units have died, set once the replay summary is registered.
+    pub death_heatmap: DeathHeatmap,
+    /// Clusters the deaths into battles.
+    pub battle_detector: BattleDetector,
+    /// The game loop of the event being drawn.
+    pub game_loop: i64,
+    /// The ordinal of the event being drawn.
+    pub event_index: usize,
+    /// The last point the units were ordered to move to, with its game loop, by unit tag.
+    pub unit_target_points: HashMap<i64, (i64, (f32, f32))>,
+    /// The hotspots whose countdown has not started yet, sorted by game loop.
+    pub hotspots: VecDeque<Hotspot>,
+    /// The hotspots with a countdown marker, with the seconds left last drawn.
+    pub active_hotspots: Vec<(Hotspot, i64)>,
+    /// What the players see, set once the replay summary is registered.
+    pub vision_map: VisionMap,
+    /// Counts the actions of the players.
+    pub activity_tracker: ActivityTracker,
+    /// The game loop the activity was last drawn at.
+    pub activity_loop: i64,
+    /// Collects the build order of each player.
+    pub build_order_extractor: BuildOrderExtractor,
+    /// How closely a player followed a reference build order.
+    pub build_order_grade: Option<BuildOrderGrade>,
+    /// The research spans of the upgrades of each player.
+    pub upgrade_timeline: UpgradeTimeline,
+    /// The busy and idle spans of the production structures.
+    pub production_tracker: ProductionTracker,
+}
+
+impl RerunSink {
+    pub fn new(recording_stream: RecordingStream) -> Self {
+        Self {
+            recording_stream,
+            summary: ReplaySummary::default(),
+            player_colors: PlayerColors::default(),
+            unit_catalog: UnitCatalog::bundled(),
+            render_config: RenderConfig::default(),
+            unit_tag_recycles: HashMap::new(),
+            dead_unit_tags: HashSet::new(),
+            unit_lifecycle: RefCell::new(UnitLifecycle::default()),
+            unit_types: HashMap::new(),
+            unit_trails: HashMap::new(),
+            unit_target_points: HashMap::new(),
+            death_heatmap: DeathHeatmap::default(),
+            battle_detector: BattleDetector::new(UnitCatalog::bundled()),
+            game_loop: 0,
+            event_index: 0,
+            hotspots: VecDeque::new(),
+            active_hotspots: vec![],
+            vision_map: VisionMap::default(),
+            activity_tracker: ActivityTracker::default(),
+            activity_loop: 0,
+            build_order_extractor: BuildOrderExtractor::new(UnitCatalog::bundled()),
+            build_order_grade: None,
+            upgrade_timeline: UpgradeTimeline::default(),
+            production_tracker: ProductionTracker::new(UnitCatalog::bundled()),
+        }
+    }
+
+    /// Sets the catalog used to size and color the units.
+    pub fn with_unit_catalog(mut self, unit_catalog: UnitCatalog) -> Self {
+        self.battle_detector =
+            BattleDetector::new(unit_catalog.clone()).with_config(self.battle_detector.config);
+        self.build_order_extractor = BuildOrderExtractor::new(unit_catalog.clone());
+        self.production_tracker = ProductionTracker::new(unit_catalog.clone())
+            .with_config(self.production_tracker.config);
+        self.unit_catalog = unit_catalog;
+        self
+    }
+
+    /// Sets when deaths are considered part of the same battle.
+    pub fn with_battle_config(mut self, battle_config: BattleConfig) -> Self {
+        self.battle_detector = self.battle_detector.with_config(battle_config);
+        self
+    }
+
+    /// Sets how the activity of the players is measured.
+    pub fn with_activity_config(mut self, activity_config: ActivityConfig) -> Self {
+        self.activity_tracker = self.activity_tracker.with_config(activity_config);
+        self
+    }
+
+    /// Sets when the production structures are considered idle.
+    pub fn with_production_config(mut self, production_config: ProductionConfig) -> Self {
+        self.production_tracker = self.production_tracker.with_config(production_config);
+        self
+    }
+
+    /// Sets the views and Z axis layout the units are drawn with.
+    pub fn with_render_config(mut self, render_config: RenderConfig) -> Self {
+        self.render_config = render_config;
+        self
+    }
+
+    /// Sets the game_loop and game_time timelines, the event_index timeline is left untouched.
+    pub fn set_game_loop(&self, game_loop: i64) {
+        self.recording_stream
+            .set_time_sequence("game_loop", game_loop);
+        // The real-time clock, this depends on the game speed, i.e. 22.4 game loops per second
+        // at Faster.
+        self.recording_stream.set_duration_secs(
+            "game_time",
+            self.summary.game_speed.game_loop_secs(game_loop),
+        );
+    }
+
+    /// Sets the time of an entry logged back in time, before the event being drawn. The entry
+    /// belongs to no single event, so it is left out of the event_index timeline rather than
+    /// ordered with the event being drawn.
+    pub fn set_past_game_loop(&self, game_loop: i64) {
+        self.recording_stream.disable_timeline("event_index");
+        self.set_game_loop(game_loop);
+    }
+
+    /// Sets the timelines back to the event being drawn after logging back in time.
+    pub fn restore_time(&self) {
+        self.recording_stream
+            .set_time_sequence("event_index", self.event_index as i64);
+        self.set_game_loop(self.game_loop);
+    }
+
+    /// Returns the Z coordinate of an event in the 3D view.
+    pub fn z(&self, game_loop: i64) -> f32 {
+        self.render_config.z(game_loop, self.summary.game_speed)
+    }
+
+    /// Whether the time-as-Z 3D entities should be logged.
+    pub fn has_3d(&self) -> bool {
+        self.render_config.view_mode.has_3d()
+    }
+
+    /// Whether the `Map2D/` entities should be logged.
+    pub fn has_2d(&self) -> bool {
+        self.render_config.view_mode.has_2d()
+    }
+
+    /// Sets the hotspots to draw a countdown marker ahead of, see `RenderConfig::lookahead_secs`.
+    pub fn with_hotspots(mut self, mut hotspots: Vec<Hotspot>) -> Self {
+        hotspots.sort_by_key(|hotspot| hotspot.game_loop);
+        self.hotspots = hotspots.into();
+        self
+    }
+
+    /// Sets the grade of a player against a reference build order, its steps are drawn on the
+    /// timeline under `BuildOrderGrade/`
+    pub fn with_build_order_grade(mut self, build_order_grade: BuildOrderGrade) -> Self {
+        self.build_order_grade = Some(build_order_grade);
+        self
+    }
+
+    /// Returns the radius and color to draw a unit with.
+    /// Selected units are drawn with twice their radius.
+    pub fn unit_sized_color(&self, unit: &SC2Unit) -> (f32, [u8; 4]) {
+        let team_id = unit
+            .user_id
+            .and_then(|player_id| self.summary.player_by_id(player_id))
+            .map(|player| player.team_id);
+        let (radius, color) = get_unit_sized_color(
+            &unit.name,
+            unit.user_id.unwrap_or(99u8) as i64,
+            team_id,
+            &self.player_colors,
+            &self.unit_catalog,
+        );
+        if unit.is_selected {
+            (radius * 2., color)
+        } else {
+            (radius, color)
+        }
+    }
+
+    /// Returns the tracker player id of a game events user id, None when it is not a player.
+    pub fn user_player_id(&self, user_id: i64) -> Option<u8> {
+        self.summary.user_player_id(user_id)
+    }
+
+    /// Returns the full tag of a unit, its tag index and recycle count.
+    /// Units that have not been seen in a lifecycle event have a recycle count of 0.
+    pub fn unit_tag(&self, unit: &SC2Unit) -> i64 {
+        s2protocol::tracker_events::unit_tag(
+            unit.tag_index,
+            self.unit_tag_recycles
+                .get(&unit.tag_index)
+                .copied()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Whether the unit has died, i.e. the game events still carry it in the selections.
+    pub fn is_unit_dead(&self, unit: &SC2Unit) -> bool {
+        self.dead_unit_tags.contains(&self.unit_tag(unit))
+    }
+
+    /// Returns the entity path of a unit, grouped by its owner and category so that they can be
+    /// toggled independently in the viewer, i.e. `Player/1/Army/11010049`
+    /// The path is keyed by the full unit tag, so that units with a recycled tag index are not
+    /// merged, and it does not contain the unit type so that morphing units, i.e. Hatchery to
+    /// Lair, stay in the same entity. The unit type is logged as the label of its points.
+    pub fn unit_path(&self, unit: &SC2Unit) -> String {
+        self.unit_path_as(unit, &unit.name)
+    }
+
+    /// Returns the entity path a unit would have if it was of the given type.
+    /// Only the category of the type is part of the path.
+    pub fn unit_path_as(&self, unit: &SC2Unit, unit_name: &str) -> String {
+        format!(
+            "Player/{}/{:?}/{}",
+            unit.user_id.unwrap_or(99u8),
+            get_unit_category(unit_name, &self.unit_catalog),
+            self.unit_tag(unit)
+        )
+    }
+
+    /// Returns the path of a unit entity, i.e. `Player/1/Army/11010049/Position`
+    /// The path is registered in the unit lifecycle so that it is cleared when the unit dies.
+    pub fn unit_entity_path(&self, unit: &SC2Unit, suffix: &str) -> String {
+        let entity_path = format!("{}/{}", self.unit_path(unit), suffix);
+        self.unit_lifecycle
+            .borrow_mut()
+            .register(self.unit_tag(unit), &entity_path);
+        entity_path
+    }
+
+    /// Clears all the entities logged for a unit.
+    pub fn clear_unit(&self, unit_tag: i64) -> Result<(), SwarmyError> {
+        let entity_paths = self.unit_lifecycle.borrow_mut().unregister(unit_tag);
+        for entity_path in entity_paths {
+            self.clear(&entity_path)?;
+        }
+        Ok(())
+    }
+
+    /// Logs a point at a map position.
+    /// In the 3D view the game loop is the Z axis, in the 2D view the point moves in place.
+    /// The label is available in the selection panel but is not drawn in the views.
+    pub fn log_point(
+        &self,
+        entity_path: &str,
+        pos: (f32, f32),
+        game_loop: i64,
+        radius: f32,
+        color: Option<[u8; 4]>,
+        label: Option<&str>,
+    ) -> Result<(), SwarmyError> {
+        if self.has_3d() {
+            let mut points = rerun::Points3D::new([(pos.0, pos.1, self.z(game_loop))])
+                //.with_draw_order(game_loop as f32)
+                .with_radii([radius]);
+            if let Some(color) = color {
+                points = points.with_colors([color]);
+            }
+            if let Some(label) = label {
+                points = points.with_labels([label]).with_show_labels(false);
+            }
+            self.recording_stream.log(entity_path, &points)?;
+        }
+        if self.has_2d() {
+            let mut points = rerun::Points2D::new([pos]).with_radii([radius]);
+            if let Some(color) = color {
+                points = points.with_colors([color]);
+            }
+            if let Some(label) = label {
+                points = points.with_labels([label]).with_show_labels(false);
+            }
+            self.recording_stream
+                .log(format!("Map2D/{}", entity_path), &points)?;
+        }
+        Ok(())
+    }
+
+    /// Logs an arrow from a map position.
+    pub fn log_arrow(
+        &self,
+        entity_path: &str,
+        origin: (f32, f32),
+        vector: (f32, f32),
+        game_loop: i64,
+        color: [u8; 4],
+    ) -> Result<(), SwarmyError> {
+        if self.has_3d() {
+            self.recording_stream.log(
+                entity_path,
+                &rerun::Arrows3D::from_vectors([(vector.0, vector.1, 0.)])
+                    .with_origins([(origin.0, origin.1, self.z(game_loop))])
+                    .with_colors([color]),
+            )?;
+        }
+        if self.has_2d() {
+            self.recording_stream.log(
+                format!("Map2D/{}", entity_path),
+                &rerun::Arrows2D::from_vectors([vector])
+                    .with_origins([origin])
+                    .with_colors([color]),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Logs a path through map positions, each with the game loop it was visited at.
+    pub fn log_line_strip(
+        &self,
+        entity_path: &str,
+        positions: &[(i64, (f32, f32))],
+        radius: f32,
+        color: [u8; 4],
+    ) -> Result<(), SwarmyError> {
+        if self.has_3d() {
+            let strip: Vec<(f32, f32, f32)> = positions
+                .iter()
+                .map(|(game_loop, (x, y))| (*x, *y, self.z(*game_loop)))
+                .collect();
+            self.recording_stream.log(
+                entity_path,
+                &rerun::LineStrips3D::new([strip])
+                    .with_radii([radius])
+                    .with_colors([color]),
+            )?;
+        }
+        if self.has_2d() {
+            let strip: Vec<(f32, f32)> = positions.iter().map(|(_, pos)| *pos).collect();
+            self.recording_stream.log(
+                format!("Map2D/{}", entity_path),
+                &rerun::LineStrips2D::new([strip])
+                    .with_radii([radius])
+                    .with_colors([color]),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Draws the countdown markers of the hotspots that happen within the lookahead seconds of
+    /// the current game loop, i.e. `Battle: 23 deaths in 5s`, and clears the ones that started.
+    /// A marker is only logged when its seconds left change.
+    pub fn update_hotspots(&mut self) -> Result<(), SwarmyError> {
+        let Some(lookahead_secs) = self.render_config.lookahead_secs else {
+            return Ok(());
+        };
+        let loops_per_second = self.summary.game_speed.game_loops_per_second();
+        let lookahead_loops = (lookahead_secs as f64 * loops_per_second) as i64;
+        while self
+            .hotspots
+            .front()
+            .is_some_and(|hotspot| hotspot.game_loop - lookahead_loops <= self.game_loop)
+        {
+            let hotspot = self.hotspots.pop_front().expect("The front was checked");
+            self.recording_stream.log(
+                "Hotspot/Log",
+                &rerun::TextLog::new(format!(
+                    "{} in {:.0}s at ({:.0}, {:.0})",
+                    hotspot.label,
+                    (hotspot.game_loop - self.game_loop) as f64 / loops_per_second,
+                    hotspot.pos.0,
+                    hotspot.pos.1
+                ))
+                .with_level(rerun::TextLogLevel::INFO),
+            )?;
+            self.active_hotspots.push((hotspot, -1));
+        }
+        let mut active_hotspots = std::mem::take(&mut self.active_hotspots);
+        for (hotspot, shown_secs) in active_hotspots.iter_mut() {
+            let entity_path = format!("Hotspot/{:?}/{}", hotspot.kind, hotspot.game_loop);
+            if hotspot.game_loop <= self.game_loop {
+                self.clear(&entity_path)?;
+                continue;
+            }
+            let secs_left =
+                ((hotspot.game_loop - self.game_loop) as f64 / loops_per_second).ceil() as i64;
+            if secs_left == *shown_secs {
+                continue;
+            }
+            *shown_secs = secs_left;
+            let label = format!("{} in {}s", hotspot.label, secs_left);
+            let color = match hotspot.kind {
+                HotspotKind::Battle => FREYA_ORANGE,
+                HotspotKind::Death => FREYA_RED,
+                HotspotKind::Drop => FREYA_VIOLET,
+            };
+            if self.has_3d() {
+                self.recording_stream.log(
+                    entity_path.clone(),
+                    &rerun::Points3D::new([(hotspot.pos.0, hotspot.pos.1, self.z(self.game_loop))])
+                        .with_radii([hotspot.radius])
+                        .with_labels([label.clone()])
+                        .with_colors([color]),
+                )?;
+            }
+            if self.has_2d() {
+                self.recording_stream.log(
+                    format!("Map2D/{}", entity_path),
+                    &rerun::Points2D::new([hotspot.pos])
+                        .with_radii([hotspot.radius])
+                        .with_labels([label])
+                        .with_colors([color]),
+                )?;
+            }
+        }
+        active_hotspots.retain(|(hotspot, _)| hotspot.game_loop > self.game_loop);
+        self.active_hotspots = active_hotspots;
+        Ok(())
+    }
+
+    /// Removes the deaths that went out of the heatmap window and redraws their grids, so that
+    /// the heatmap fades while there are no new deaths.
+    pub fn update_death_heatmap(&mut self) -> Result<(), SwarmyError> {
+        let changed_grids = self.death_heatmap.expire_deaths(self.game_loop);
+        tracker_events::log_death_heatmap(changed_grids, self)
+    }
+
+    /// Draws what the players whose vision changed see now and have ever scouted, at most once
+    /// every vision step.
+    pub fn update_vision(&mut self) -> Result<(), SwarmyError> {
+        let Some(vision_step_secs) = self.render_config.vision_step_secs else {
+            return Ok(());
+        };
+        let step_loops =
+            (vision_step_secs as f64 * self.summary.game_speed.game_loops_per_second()) as i64;
+        for player_id in self
+            .vision_map
+            .take_changed_players(self.game_loop, step_loops)
+        {
+            let color = self.player_colors.player_color(player_id as i64);
+            let (visible, scouted) = self.vision_map.update(player_id, color);
+            let resolution = self.vision_map.resolution();
+            self.recording_stream.log(
+                format!("Vision/Player/{}/Visible", player_id),
+                &rerun::Image::from_rgba32(visible, resolution),
+            )?;
+            self.recording_stream.log(
+                format!("Vision/Player/{}/Scouted", player_id),
+                &rerun::Image::from_rgba32(scouted, resolution),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Clears an entity and its children in the views it was drawn into.
+    pub fn clear(&self, entity_path: &str) -> Result<(), SwarmyError> {
+        if self.has_3d() {
+            self.recording_stream
+                .log(entity_path, &rerun::Clear::recursive())?;
+        }
+        if self.has_2d() {
+            self.recording_stream
+                .log(format!("Map2D/{}", entity_path), &rerun::Clear::recursive())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReplaySink for RerunSink {
+    fn register_summary(&mut self, summary: &ReplaySummary) -> Result<(), SwarmyError> {
+        self.summary = summary.clone();
+        self.player_colors = PlayerColors::new(summary);
+        self.battle_detector.register_summary(summary)?;
+        self.activity_tracker.register_summary(summary)?;
+        // Some maps report a 0 size, the largest map size is used then.
+        let map_size = match summary.map_size {
+            (0, _) | (_, 0) => (256, 256),
+            map_size => map_size,
+        };
+        let window_loops = self
+            .render_config
+            .heatmap_window_secs
+            .map(|secs| (secs as f64 * summary.game_speed.game_loops_per_second()) as i64);
+        self.death_heatmap =
+            DeathHeatmap::new(map_size, self.render_config.heatmap_cell_size, window_loops);
+        self.vision_map = VisionMap::new(map_size, self.render_config.vision_cell_size);
+        self.recording_stream.log_static(
+            "Summary",
+            &rerun::TextDocument::from_markdown(summary.to_markdown()),
+        )?;
+        tracker_events::register_player_stats_series(self)?;
+        game_events::register_activity_series(self)?;
+        self.upgrade_timeline.register_summary(summary)?;
+        self.production_tracker.register_summary(summary)?;
+        self.build_order_extractor.register_summary(summary)
+    }
+
+    fn set_time(&mut self, event_index: usize, game_loop: i64) {
+        // Both the tracker and the game events are set in the same timelines.
+        self.recording_stream
+            .set_time_sequence("event_index", event_index as i64);
+        self.set_game_loop(game_loop);
+        self.game_loop = game_loop;
+        self.event_index = event_index;
+        self.production_tracker.set_time(event_index, game_loop);
+        if let Err(err) = self.update_hotspots() {
+            tracing::error!("Unable to draw the hotspots: {:?}", err);
+        }
+        if let Err(err) = self.update_vision() {
+            tracing::error!("Unable to draw the vision: {:?}", err);
+        }
+        if let Err(err) = self.update_death_heatmap() {
+            tracing::error!("Unable to draw the death heatmap: {:?}", err);
+        }
+        // The rolling activity decays when the players are idle, so it is sampled every second
+        // rather than on each action.
+        let step_loops = self.summary.game_speed.game_loops_per_second() as i64;
+        if game_loop >= self.activity_loop + step_loops {
+            self.activity_loop = game_loop;
+            if let Err(err) = game_events::register_activity(self) {
+                tracing::error!("Unable to draw the activity: {:?}", err);
+            }
+        }
+    }
+
+    fn register_unit_init(
+        &mut self,
+        unit_init: &UnitInitEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.build_order_extractor.register_unit_init(
+            unit_init,
+            change_hint.clone(),
+            tracker_loop,
+        )?;
+        self.production_tracker
+            .register_unit_init(unit_init, change_hint.clone(), tracker_loop)?;
+        tracker_events::register_unit_init(unit_init, change_hint, self, tracker_loop)
+    }
+
+    fn register_unit_done(
+        &mut self,
+        unit_done: &UnitDoneEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.production_tracker
+            .register_unit_done(unit_done, change_hint, tracker_loop)
+    }
+
+    fn register_unit_born(
+        &mut self,
+        unit_born: &UnitBornEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.build_order_extractor.register_unit_born(
+            unit_born,
+            change_hint.clone(),
+            tracker_loop,
+        )?;
+        self.production_tracker
+            .register_unit_born(unit_born, change_hint.clone(), tracker_loop)?;
+        tracker_events::register_unit_born(unit_born, change_hint, self, tracker_loop)
+    }
+
+    fn register_unit_type_change(
+        &mut self,
+        unit_type_change: &UnitTypeChangeEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.build_order_extractor.register_unit_type_change(
+            unit_type_change,
+            change_hint.clone(),
+            tracker_loop,
+        )?;
+        self.production_tracker.register_unit_type_change(
+            unit_type_change,
+            change_hint.clone(),
+            tracker_loop,
+        )?;
+        tracker_events::register_unit_type_change(unit_type_change, change_hint, self, tracker_loop)
+    }
+
+    fn register_unit_died(
+        &mut self,
+        unit_dead: &UnitDiedEvent,
+        change_hint: UnitChangeHint,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.build_order_extractor.register_unit_died(
+            unit_dead,
+            change_hint.clone(),
+            tracker_loop,
+        )?;
+        self.production_tracker
+            .register_unit_died(unit_dead, change_hint.clone(), tracker_loop)?;
+        tracker_events::register_unit_died(unit_dead, change_hint, self, tracker_loop)
+    }
+
+    fn register_unit_position(
+        &mut self,
+        change_hint: UnitChangeHint,
+        unit_pos: UnitPositionsEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        tracker_events::register_unit_position(change_hint, unit_pos, self, tracker_loop)
+    }
+
+    fn register_player_stats(
+        &mut self,
+        player_stats: &PlayerStatsEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.production_tracker
+            .register_player_stats(player_stats, tracker_loop)?;
+        tracker_events::register_player_stats(player_stats, self)
+    }
+
+    fn register_upgrade(
+        &mut self,
+        upgrade: &UpgradeEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.build_order_extractor
+            .register_upgrade(upgrade, tracker_loop)?;
+        self.production_tracker
+            .register_upgrade(upgrade, tracker_loop)?;
+        tracker_events::register_upgrade(upgrade, self, tracker_loop)
+    }
+
+    fn register_game_event(
+        &mut self,
+        user_id: i64,
+        game_event: &ReplayGameEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.activity_tracker
+            .register_game_event(user_id, game_event, game_loop)
+    }
+
+    fn register_camera_save(
+        &mut self,
+        user_id: i64,
+        camera_save: &CameraSaveEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        game_events::register_camera_save(user_id, camera_save, self, game_loop)
+    }
+
+    fn register_camera_update(
+        &mut self,
+        user_id: i64,
+        camera_update: &CameraUpdateEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        game_events::register_camera_update(user_id, camera_update, self, game_loop)
+    }
+
+    fn register_cmd(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        game_cmd: &GameSCmdEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        // The production only needs the research commands, not the units that issued them.
+        self.production_tracker
+            .register_cmd(user_id, UnitChangeHint::None, game_cmd, game_loop)?;
+        game_events::register_cmd(user_id, change_hint, game_cmd, self, game_loop)
+    }
+
+    fn register_update_target_point(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        target_point: &GameSMapCoord3D,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        game_events::register_update_target_point(
+            user_id,
+            change_hint,
+            target_point,
+            self,
+            game_loop,
+        )
+    }
+
+    fn register_update_target_unit(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        target_unit: &GameSCmdDataTargetUnit,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        game_events::register_update_target_unit(user_id, change_hint, target_unit, self, game_loop)
+    }
+
+    fn register_selection_delta(
+        &mut self,
+        change_hint: UnitChangeHint,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        game_events::register_selection_delta(change_hint, self, game_loop)
+    }
+
+    fn update_control_group(
+        &mut self,
+        change_hint: UnitChangeHint,
+        ctrl_group_evt: &GameSControlGroupUpdateEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        game_events::update_control_group(change_hint, ctrl_group_evt, self, game_loop)
+    }
+
+    fn handle_chat_message(
+        &mut self,
+        user_id: i64,
+        change_hint: UnitChangeHint,
+        chat_message: &GameSTriggerChatMessageEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        game_events::handle_chat_message(user_id, change_hint, chat_message, self, game_loop)
+    }
+
+    fn finish(&mut self) -> Result<(), SwarmyError> {
+        let battles = self.battle_detector.close_battles(i64::MAX);
+        tracker_events::register_battles(battles, self)?;
+        game_events::register_activity_summary(self)?;
+        tracker_events::register_build_orders(self)?;
+        tracker_events::register_build_order_grade(self)?;
+        tracker_events::register_production(self)?;
+        self.recording_stream.flush_blocking();
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Estimated unit positions between the sparse tracker position samples.
+//! The UnitPositionsEvent only arrive every ~15 seconds of game time, in between the units are
+//! assumed to move at a constant speed, optionally walking through the last point they were
+//! ordered to move to.
+
+use super::*;
+
+/// Returns the positions between two samples every `step_loops` game loops, neither sample is
+/// included. When a target point is provided the unit walks through it on its way to the
+/// second sample.
+pub fn interpolate_positions(
+    from: (i64, (f32, f32)),
+    to: (i64, (f32, f32)),
+    target_point: Option<(f32, f32)>,
+    step_loops: i64,
+) -> Vec<(i64, (f32, f32))> {
+    let (from_loop, from_pos) = from;
+    let (to_loop, to_pos) = to;
+    if step_loops <= 0 || to_loop - from_loop <= step_loops {
+        return vec![];
+    }
+    let mut waypoints = vec![from_pos];
+    waypoints.extend(target_point);
+    waypoints.push(to_pos);
+    let segment_lengths: Vec<f32> = waypoints
+        .windows(2)
+        .map(|segment| distance(segment[0], segment[1]))
+        .collect();
+    let total_length: f32 = segment_lengths.iter().sum();
+    if total_length == 0. {
+        // The unit did not move, there is nothing to estimate.
+        return vec![];
+    }
+    let mut res = vec![];
+    let mut game_loop = from_loop + step_loops;
+    while game_loop < to_loop {
+        let progress = (game_loop - from_loop) as f32 / (to_loop - from_loop) as f32;
+        let mut remaining = progress * total_length;
+        for (segment, length) in waypoints.windows(2).zip(&segment_lengths) {
+            if remaining <= *length {
+                let ratio = if *length == 0. {
+                    0.
+                } else {
+                    remaining / length
+                };
+                res.push((
+                    game_loop,
+                    (
+                        segment[0].0 + (segment[1].0 - segment[0].0) * ratio,
+                        segment[0].1 + (segment[1].1 - segment[0].1) * ratio,
+                    ),
+                ));
+                break;
+            }
+            remaining -= length;
+        }
+        game_loop += step_loops;
+    }
+    res
+}
+
+fn distance(from: (f32, f32), to: (f32, f32)) -> f32 {
+    ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2)).sqrt()
+}
+
+/// Draws the estimated positions of a unit between its previous position sample and the
+/// current one, each at its own game loop. These are logged under `Estimated` with a
+/// translucent color so that they are not confused with the real samples.
+pub fn register_unit_interpolation(
+    unit: &SC2Unit,
+    rerun_sink: &RerunSink,
+    tracker_loop: i64,
+) -> Result<(), SwarmyError> {
+    let Some(step_loops) = rerun_sink.render_config.interpolation_step_loops else {
+        return Ok(());
+    };
+    let Some(previous) = rerun_sink
+        .unit_trails
+        .get(&rerun_sink.unit_tag(unit))
+        .and_then(|trail| trail.back())
+        .copied()
+    else {
+        return Ok(());
+    };
+    let target_point = if rerun_sink.render_config.interpolate_with_targets {
+        rerun_sink
+            .unit_target_points
+            .get(&rerun_sink.unit_tag(unit))
+            .filter(|(cmd_loop, _)| *cmd_loop > previous.0 && *cmd_loop < tracker_loop)
+            .map(|(_, target_point)| *target_point)
+    } else {
+        None
+    };
+    let estimates = interpolate_positions(
+        previous,
+        (tracker_loop, (unit.pos.x(), unit.pos.y())),
+        target_point,
+        step_loops,
+    );
+    if estimates.is_empty() {
+        return Ok(());
+    }
+    let (unit_radius, mut unit_color) = rerun_sink.unit_sized_color(unit);
+    unit_color[3] = 0x60;
+    let entity_path = rerun_sink.unit_entity_path(unit, "Estimated");
+    for (game_loop, pos) in estimates {
+        rerun_sink.set_past_game_loop(game_loop);
+        rerun_sink.log_point(
+            &entity_path,
+            pos,
+            game_loop,
+            unit_radius,
+            Some(unit_color),
+            Some(&unit.name),
+        )?;
+    }
+    rerun_sink.restore_time();
+    Ok(())
+}
@@ -1,35 +1,36 @@
 //! Tracker Event registration.
 use super::*;
-use convert_case::{Case, Casing};
-use rerun::RecordingStream;
 use s2protocol::{tracker_events::*, SC2Unit, UnitChangeHint};
 
+pub mod interpolation;
+pub use interpolation::*;
+
 pub fn register_unit(
     unit: &SC2Unit,
     creator: &Option<SC2Unit>,
     path_suffix: &'static str,
-    recording_stream: &RecordingStream,
+    rerun_sink: &RerunSink,
     tracker_loop: i64,
     unit_tag_index: u32,
 ) -> Result<(), SwarmyError> {
     let user_id = unit.user_id.unwrap_or(99u8) as i64;
     let unit_pos_x = unit.pos.x();
     let unit_pos_y = unit.pos.y();
-    recording_stream.log(
-        format!("Unit/{}/{}/{}", unit.name, unit_tag_index, path_suffix),
-        &rerun::Points3D::new([(unit_pos_x, unit_pos_y, (tracker_loop as f32 / 100.))])
-            //.with_labels([unit.name.clone()])
-            //.with_draw_order(tracker_loop as f32)
-            //.with_keypoint_ids([unit_tag_index as u64])
-            .with_colors([unit.color])
-            .with_radii([unit.radius]),
+    let (unit_radius, unit_color) = rerun_sink.unit_sized_color(unit);
+    rerun_sink.log_point(
+        &rerun_sink.unit_entity_path(unit, path_suffix),
+        (unit_pos_x, unit_pos_y),
+        tracker_loop,
+        unit_radius,
+        Some(unit_color),
+        Some(&unit.name),
     )?;
     let mut unit_name_trunc = unit.name.clone();
     unit_name_trunc.truncate(8);
     if let Some(creator) = creator {
         let creator_name_trunc = creator.name.clone();
-        recording_stream.log(
-            format!("Unit/{}/{}/Creator", unit.name, unit_tag_index),
+        rerun_sink.recording_stream.log(
+            rerun_sink.unit_entity_path(unit, "Creator"),
             &rerun::TextLog::new(format!(
                 "U:{user_id} [{0:8}@{unit_tag_index:3}] created by {1:8}",
                 unit_name_trunc, creator_name_trunc
@@ -39,7 +40,7 @@ pub fn register_unit(
     }
     let mut path_suffix: String = path_suffix.into();
     path_suffix.truncate(4);
-    recording_stream.log(
+    rerun_sink.recording_stream.log(
         format!("Log/{}", path_suffix),
         &rerun::TextLog::new(format!(
             "U:{user_id} [{0:16}@{unit_tag_index:3}] pos: ({unit_pos_x:3},{unit_pos_y:3})",
@@ -53,18 +54,25 @@ pub fn register_unit(
 pub fn register_unit_init(
     unit_init: &UnitInitEvent,
     change_hint: UnitChangeHint,
-    recording_stream: &RecordingStream,
+    rerun_sink: &mut RerunSink,
     tracker_loop: i64,
 ) -> Result<(), SwarmyError> {
+    rerun_sink
+        .unit_tag_recycles
+        .insert(unit_init.unit_tag_index, unit_init.unit_tag_recycle);
     if let UnitChangeHint::Registered { unit, creator } = change_hint {
+        rerun_sink
+            .unit_types
+            .insert(rerun_sink.unit_tag(&unit), unit.name.clone());
         register_unit(
             &unit,
             &creator,
             "Init",
-            recording_stream,
+            rerun_sink,
             tracker_loop,
             unit_init.unit_tag_index,
         )?;
+        register_unit_vision(&unit, rerun_sink);
     } else {
         tracing::info!(
             "UnitInitEvent {:?} with unexpected UnitChangeHint: {:?}",
@@ -78,18 +86,36 @@ pub fn register_unit_init(
 pub fn register_unit_type_change(
     unit_type_change: &UnitTypeChangeEvent,
     change_hint: UnitChangeHint,
-    recording_stream: &RecordingStream,
+    rerun_sink: &mut RerunSink,
     tracker_loop: i64,
 ) -> Result<(), SwarmyError> {
+    rerun_sink.unit_tag_recycles.insert(
+        unit_type_change.unit_tag_index,
+        unit_type_change.unit_tag_recycle,
+    );
     if let UnitChangeHint::Registered { unit, creator } = change_hint {
+        let unit_tag = rerun_sink.unit_tag(&unit);
+        if let Some(previous_type) = rerun_sink.unit_types.insert(unit_tag, unit.name.clone()) {
+            // The unit stays in the same entity unless its category changes, i.e. an Egg into
+            // a Drone, its entities are then cleared and registered again under the new path.
+            if rerun_sink.unit_path_as(&unit, &previous_type) != rerun_sink.unit_path(&unit) {
+                rerun_sink.clear_unit(unit_tag)?;
+            }
+            rerun_sink.recording_stream.log(
+                rerun_sink.unit_entity_path(&unit, "TypeHistory"),
+                &rerun::TextLog::new(format!("{} -> {}", previous_type, unit.name))
+                    .with_level(rerun::TextLogLevel::TRACE),
+            )?;
+        }
         register_unit(
             &unit,
             &creator,
             "TypeChange",
-            recording_stream,
+            rerun_sink,
             tracker_loop,
             unit_type_change.unit_tag_index,
         )?;
+        register_unit_vision(&unit, rerun_sink);
     } else {
         tracing::info!(
             "UnitTypeChangeEvent {:?} with unexpected UnitChangeHint: {:?}",
@@ -103,18 +129,25 @@ pub fn register_unit_type_change(
 pub fn register_unit_born(
     unit_born: &UnitBornEvent,
     change_hint: UnitChangeHint,
-    recording_stream: &RecordingStream,
+    rerun_sink: &mut RerunSink,
     tracker_loop: i64,
 ) -> Result<(), SwarmyError> {
+    rerun_sink
+        .unit_tag_recycles
+        .insert(unit_born.unit_tag_index, unit_born.unit_tag_recycle);
     if let UnitChangeHint::Registered { unit, creator } = change_hint {
+        rerun_sink
+            .unit_types
+            .insert(rerun_sink.unit_tag(&unit), unit.name.clone());
         register_unit(
             &unit,
             &creator,
             "Born",
-            recording_stream,
+            rerun_sink,
             tracker_loop,
             unit_born.unit_tag_index,
         )?;
+        register_unit_vision(&unit, rerun_sink);
     } else {
         tracing::info!(
             "Unit Born event {:?} with unexpected UnitChangeHint: {:?}",
@@ -128,15 +161,18 @@ pub fn register_unit_born(
 pub fn register_unit_died(
     unit_dead: &UnitDiedEvent,
     change_hint: UnitChangeHint,
-    recording_stream: &RecordingStream,
+    rerun_sink: &mut RerunSink,
     tracker_loop: i64,
 ) -> Result<(), SwarmyError> {
     if let UnitChangeHint::Unregistered { killer, killed } = change_hint {
+        rerun_sink
+            .unit_tag_recycles
+            .insert(unit_dead.unit_tag_index, unit_dead.unit_tag_recycle);
         let user_id = killed.user_id.unwrap_or(99u8) as i64;
         let mut unit_name_trunc = killed.name.clone();
         unit_name_trunc.truncate(8);
         let unit_tag_index = killed.tag_index;
-        recording_stream.log(
+        rerun_sink.recording_stream.log(
             "Log/Died",
             &rerun::TextLog::new(format!(
                 "U:{user_id} [{0:8}@{unit_tag_index:3}]",
@@ -144,52 +180,31 @@ pub fn register_unit_died(
             ))
             .with_level(rerun::TextLogLevel::TRACE),
         )?;
-        // Clear up the killed unit target
-        recording_stream.log(
-            format!(
-                "Unit/{}/{}/Target",
-                killed.name.clone(),
-                unit_dead.unit_tag_index
-            ),
-            &rerun::Clear::recursive(),
-        )?;
-        // Clear up the killed unit born data
-        recording_stream.log(
-            format!(
-                "Unit/{}/{}/Born",
-                killed.name.clone(),
-                unit_dead.unit_tag_index
-            ),
-            &rerun::Clear::recursive(),
-        )?;
-        // Clear up the killed unit init data
-        recording_stream.log(
-            format!(
-                "Unit/{}/{}/Init",
-                killed.name.clone(),
-                unit_dead.unit_tag_index
-            ),
-            &rerun::Clear::recursive(),
-        )?;
+        // Clear up every entity logged for the killed unit, its positions, targets, trail, etc.
+        let killed_tag = rerun_sink.unit_tag(&killed);
+        rerun_sink.clear_unit(killed_tag)?;
+        rerun_sink.dead_unit_tags.insert(killed_tag);
+        rerun_sink.unit_trails.remove(&killed_tag);
+        rerun_sink.unit_types.remove(&killed_tag);
+        rerun_sink.unit_target_points.remove(&killed_tag);
+        rerun_sink.vision_map.remove_unit(killed_tag);
+        register_death_heatmap(&killed, unit_dead, rerun_sink, tracker_loop)?;
+        let battles = rerun_sink
+            .battle_detector
+            .add_death(&killed, unit_dead, tracker_loop);
+        register_battles(battles, rerun_sink)?;
         // Create a Path for Death so that it can be drawn on its separate pane.
-        // TODO: Create a "triangle soup", maybe something with low resolution to show regions of high
-        // activity.
-        recording_stream.log(
-            format!(
+        rerun_sink.log_point(
+            &format!(
                 "Death/{}/{}",
                 killed.name,
                 unit_tag(unit_dead.unit_tag_index, unit_dead.unit_tag_recycle)
             ),
-            &rerun::Points3D::new([(
-                unit_dead.x as f32,
-                unit_dead.y as f32,
-                tracker_loop as f32 / 100.,
-            )])
-            //.with_instance_keys([unit_tag as u64])
-            //.with_labels([killed.name.clone()])
-            //.with_draw_order(tracker_loop as f32)
-            .with_colors([FREYA_RED])
-            .with_radii([0.75]),
+            (unit_dead.x as f32, unit_dead.y as f32),
+            tracker_loop,
+            0.75,
+            Some(FREYA_RED),
+            Some(&killed.name),
         )?;
         tracing::info!("Killer Unit {:?} died at {:?}", killer, unit_dead,);
         if let (Some(unit_killer_tag_index), Some(killer_tag_recycle), Some(killer_unit)) = (
@@ -198,32 +213,22 @@ pub fn register_unit_died(
             killer,
         ) {
             let killer_tag = unit_tag(unit_killer_tag_index, killer_tag_recycle);
-            recording_stream.log(
-                format!("Kills/{}/{}", killer_unit.name, killer_tag),
-                &rerun::Points3D::new([(
-                    unit_dead.x as f32,
-                    unit_dead.y as f32,
-                    tracker_loop as f32 / 100.,
-                )])
-                //.with_labels([killed.name.clone()])
-                //.with_draw_order(tracker_loop as f32)
-                //.with_instance_keys([unit_tag as u64])
-                .with_colors([FREYA_RED])
-                .with_radii([0.75]),
+            rerun_sink.log_point(
+                &format!("Kills/{}/{}", killer_unit.name, killer_tag),
+                (unit_dead.x as f32, unit_dead.y as f32),
+                tracker_loop,
+                0.75,
+                Some(FREYA_RED),
+                Some(&killed.name),
             )?;
         } else {
-            recording_stream.log(
-                format!("Kills/{}", killed.name),
-                &rerun::Points3D::new([(
-                    unit_dead.x as f32,
-                    unit_dead.y as f32,
-                    tracker_loop as f32 / 100.,
-                )])
-                //.with_labels([killed.name.clone()])
-                //.with_draw_order(tracker_loop as f32)
-                //.with_instance_keys([unit_tag as u64])
-                .with_colors([FREYA_GREEN])
-                .with_radii([0.75]),
+            rerun_sink.log_point(
+                &format!("Kills/{}", killed.name),
+                (unit_dead.x as f32, unit_dead.y as f32),
+                tracker_loop,
+                0.75,
+                Some(FREYA_GREEN),
+                Some(&killed.name),
             )?;
         }
     } else {
@@ -236,10 +241,95 @@ pub fn register_unit_died(
     Ok(())
 }
 
+/// Adds the death to the heatmap and logs its grid as an image under
+/// `DeathHeatmap/Player/{player_id}/{category}`, colored as the player that lost the units.
+pub fn register_death_heatmap(
+    killed: &SC2Unit,
+    unit_dead: &UnitDiedEvent,
+    rerun_sink: &mut RerunSink,
+    tracker_loop: i64,
+) -> Result<(), SwarmyError> {
+    let changed_grid = rerun_sink.death_heatmap.add_death(
+        killed.user_id.unwrap_or(99u8),
+        get_unit_category(&killed.name, &rerun_sink.unit_catalog),
+        (unit_dead.x as f32, unit_dead.y as f32),
+        tracker_loop,
+    );
+    log_death_heatmap(vec![changed_grid], rerun_sink)
+}
+
+/// Logs the death heatmap grids that changed, i.e. a death was added or went out of the window.
+pub fn log_death_heatmap(
+    changed_grids: Vec<(u8, UnitCategory)>,
+    rerun_sink: &RerunSink,
+) -> Result<(), SwarmyError> {
+    for (player_id, category) in changed_grids {
+        let color = rerun_sink.player_colors.player_color(player_id as i64);
+        if let Some(pixels) = rerun_sink
+            .death_heatmap
+            .to_rgba((player_id, category), color)
+        {
+            rerun_sink.recording_stream.log(
+                format!("DeathHeatmap/Player/{}/{:?}", player_id, category),
+                &rerun::Image::from_rgba32(pixels, rerun_sink.death_heatmap.resolution()),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Logs the battles that ended, as a box over the area of the deaths and a summary of the
+/// losses. These are logged at the game loop the battle started, in the 3D view the box spans
+/// the duration of the battle and in the 2D view it is cleared once the battle ends.
+pub fn register_battles(battles: Vec<Battle>, rerun_sink: &RerunSink) -> Result<(), SwarmyError> {
+    for battle in battles {
+        let entity_path = format!("Battle/{}", battle.start_loop);
+        let center = battle.center();
+        // Leave some room around the deaths on the edges.
+        let half_size = (battle.half_size().0 + 1., battle.half_size().1 + 1.);
+        let label = format!("{} deaths", battle.deaths());
+        rerun_sink.set_past_game_loop(battle.start_loop);
+        if rerun_sink.has_3d() {
+            let start_z = rerun_sink.z(battle.start_loop);
+            let end_z = rerun_sink.z(battle.end_loop);
+            rerun_sink.recording_stream.log(
+                entity_path.clone(),
+                &rerun::Boxes3D::from_centers_and_half_sizes(
+                    [(center.0, center.1, (start_z + end_z) / 2.)],
+                    [(half_size.0, half_size.1, (end_z - start_z) / 2.)],
+                )
+                .with_labels([label.clone()])
+                .with_colors([FREYA_ORANGE]),
+            )?;
+        }
+        if rerun_sink.has_2d() {
+            rerun_sink.recording_stream.log(
+                format!("Map2D/{}", entity_path),
+                &rerun::Boxes2D::from_centers_and_half_sizes([center], [half_size])
+                    .with_labels([label])
+                    .with_colors([FREYA_ORANGE]),
+            )?;
+        }
+        rerun_sink.recording_stream.log(
+            "Battle/Log",
+            &rerun::TextLog::new(battle.summary_text(rerun_sink.summary.game_speed))
+                .with_level(rerun::TextLogLevel::INFO),
+        )?;
+        if rerun_sink.has_2d() {
+            rerun_sink.set_past_game_loop(battle.end_loop);
+            rerun_sink
+                .recording_stream
+                .log(format!("Map2D/{}", entity_path), &rerun::Clear::recursive())?;
+        }
+    }
+    rerun_sink.restore_time();
+    Ok(())
+}
+
 pub fn register_unit_position(
     change_hint: UnitChangeHint,
     unit_pos: UnitPositionsEvent,
-    recording_stream: &RecordingStream,
+    rerun_sink: &mut RerunSink,
     tracker_loop: i64,
 ) -> Result<(), SwarmyError> {
     if let UnitChangeHint::Positions(units) = change_hint {
@@ -248,10 +338,13 @@ pub fn register_unit_position(
                 &unit,
                 &None,
                 "Position",
-                recording_stream,
+                rerun_sink,
                 tracker_loop,
                 unit.tag_index,
             )?;
+            register_unit_interpolation(&unit, rerun_sink, tracker_loop)?;
+            register_unit_trail(&unit, rerun_sink, tracker_loop)?;
+            register_unit_vision(&unit, rerun_sink);
         }
     } else {
         tracing::info!(
@@ -263,78 +356,453 @@ pub fn register_unit_position(
     Ok(())
 }
 
+/// Adds the unit position to its trail and draws the path the unit has taken.
+/// When the render config has a trail window, the positions older than it are dropped.
+pub fn register_unit_trail(
+    unit: &SC2Unit,
+    rerun_sink: &mut RerunSink,
+    tracker_loop: i64,
+) -> Result<(), SwarmyError> {
+    let min_loop = rerun_sink.render_config.trail_window_secs.map(|secs| {
+        tracker_loop - (secs as f64 * rerun_sink.summary.game_speed.game_loops_per_second()) as i64
+    });
+    let unit_tag = rerun_sink.unit_tag(unit);
+    let trail = rerun_sink.unit_trails.entry(unit_tag).or_default();
+    trail.push_back((tracker_loop, (unit.pos.x(), unit.pos.y())));
+    if let Some(min_loop) = min_loop {
+        while trail
+            .front()
+            .is_some_and(|(game_loop, _)| *game_loop < min_loop)
+        {
+            trail.pop_front();
+        }
+    }
+    if trail.len() < 2 {
+        return Ok(());
+    }
+    let trail: Vec<(i64, (f32, f32))> = trail.iter().copied().collect();
+    let (_unit_radius, unit_color) = rerun_sink.unit_sized_color(unit);
+    rerun_sink.log_line_strip(
+        &rerun_sink.unit_entity_path(unit, "Trail"),
+        &trail,
+        0.05,
+        unit_color,
+    )
+}
+
+/// Updates where the unit sees from, with the sight radius of its current type.
+pub fn register_unit_vision(unit: &SC2Unit, rerun_sink: &mut RerunSink) {
+    let sight = rerun_sink
+        .unit_catalog
+        .units
+        .get(&unit.name)
+        .map(|entry| entry.sight)
+        .unwrap_or_default();
+    rerun_sink.vision_map.set_unit(
+        rerun_sink.unit_tag(unit),
+        unit.user_id.unwrap_or(99u8),
+        (unit.pos.x(), unit.pos.y()),
+        sight,
+    );
+}
+
+/// Computes the value of a series from the player stats.
+pub type PlayerStatValue = fn(&PlayerStats) -> i32;
+
+/// The player stats drawn as time series, by the name of their group under `Stats/`.
+pub const PLAYER_STAT_SERIES: [(&str, PlayerStatValue); 11] = [
+    ("Minerals", |stats| stats.minerals_current),
+    ("Gas", |stats| stats.vespene_current),
+    ("MineralsCollectionRate", |stats| {
+        stats.minerals_collection_rate
+    }),
+    ("GasCollectionRate", |stats| stats.vespene_collection_rate),
+    ("SupplyUsed", |stats| stats.food_used),
+    ("SupplyMade", |stats| stats.food_made),
+    ("Workers", |stats| stats.workers_active_count),
+    ("ArmyValue", |stats| {
+        stats.minerals_used_current_army + stats.vespene_used_current_army
+    }),
+    ("ResourcesLost", |stats| {
+        stats.minerals_lost_army
+            + stats.minerals_lost_economy
+            + stats.minerals_lost_technology
+            + stats.vespene_lost_army
+            + stats.vespene_lost_economy
+            + stats.vespene_lost_technology
+    }),
+    ("ResourcesKilled", |stats| {
+        stats.minerals_killed_army
+            + stats.minerals_killed_economy
+            + stats.minerals_killed_technology
+            + stats.vespene_killed_army
+            + stats.vespene_killed_economy
+            + stats.vespene_killed_technology
+    }),
+    ("ActiveForces", |stats| {
+        stats.minerals_used_active_forces + stats.vespene_used_active_forces
+    }),
+];
+
+/// Logs the static styling of the player stats series, a line with markers in the color of each
+/// player and named after them, i.e. `Stats/Minerals/1`
+pub fn register_player_stats_series(rerun_sink: &RerunSink) -> Result<(), SwarmyError> {
+    for player in &rerun_sink.summary.players {
+        let color = rerun_sink
+            .player_colors
+            .player_color(player.player_id as i64);
+        for (stat_name, _) in PLAYER_STAT_SERIES {
+            let entity_path = format!("Stats/{}/{}", stat_name, player.player_id);
+            rerun_sink.recording_stream.log_static(
+                entity_path.clone(),
+                &rerun::SeriesLines::new()
+                    .with_colors([color])
+                    .with_names([player.name.clone()]),
+            )?;
+            rerun_sink.recording_stream.log_static(
+                entity_path,
+                &rerun::SeriesPoints::new()
+                    .with_colors([color])
+                    .with_names([player.name.clone()])
+                    .with_marker_sizes([2.]),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Logs a sample of each of the player stats series.
 pub fn register_player_stats(
     player_stats: &PlayerStatsEvent,
-    recording_stream: &RecordingStream,
+    rerun_sink: &RerunSink,
 ) -> Result<(), SwarmyError> {
-    // TODO: record timeless the initial setup, at spawn time probably:
-    //     rec.log_timeless(
-    //     "TheStat",
-    //     &rerun::SeriesPoint::new()
-    //         .with_color([255, 0, 0])
-    //         .with_colors(user_color(player_stats.player_id as i64)),
-    //         .with_name("sin(0.01t)")
-    //         .with_marker(rerun::components::MarkerShape::Circle)
-    //         .with_marker_size(4.0),
-    // )?;
-    for stat_entity_value in player_stats.stats.as_prop_name_value_vec() {
-        println!("Stat: {}", stat_entity_value.0);
-        let entity_path = stat_entity_value.0.replace('/', "_").to_case(Case::Pascal);
-        recording_stream.log(
-            format!("{}/{}", entity_path, player_stats.player_id),
-            &rerun::Scalars::new([stat_entity_value.1 as f64]),
+    tracing::trace!(
+        "Player {} stats: {:?}",
+        player_stats.player_id,
+        player_stats.stats
+    );
+    for (stat_name, stat_value) in PLAYER_STAT_SERIES {
+        rerun_sink.recording_stream.log(
+            format!("Stats/{}/{}", stat_name, player_stats.player_id),
+            &rerun::Scalars::new([stat_value(&player_stats.stats) as f64]),
         )?;
     }
     Ok(())
 }
 
-/// Registers the tracker events to Rerun.
+/// Logs the research of an upgrade as a span under `Upgrade/{name}/{player_id}`, a line at the
+/// height of the lane of the upgrade so that the same upgrade of each player is side by side.
+/// The start and end of the research are logged in `Upgrade/Log`
+pub fn register_upgrade(
+    upgrade: &UpgradeEvent,
+    rerun_sink: &mut RerunSink,
+    tracker_loop: i64,
+) -> Result<(), SwarmyError> {
+    let Some(upgrade_span) = rerun_sink.upgrade_timeline.add_upgrade(
+        upgrade.player_id,
+        &upgrade.upgrade_type_name,
+        tracker_loop,
+    ) else {
+        return Ok(());
+    };
+    let entity_path = format!("Upgrade/{}/{}", upgrade_span.name, upgrade_span.player_id);
+    let color = rerun_sink
+        .player_colors
+        .player_color(upgrade_span.player_id as i64);
+    let label = format!("P{} {}", upgrade_span.player_id, upgrade_span.name);
+    rerun_sink.recording_stream.log_static(
+        entity_path.clone(),
+        &rerun::SeriesLines::new()
+            .with_colors([color])
+            .with_names([label.clone()])
+            .with_widths([4.]),
+    )?;
+    // The span is drawn as a line at the height of the lane of the upgrade, from its start to
+    // its end, the start is logged back in time.
+    let lane = rerun::Scalars::single(upgrade_span.lane as f64);
+    rerun_sink.set_past_game_loop(upgrade_span.start_loop);
+    rerun_sink
+        .recording_stream
+        .log(entity_path.clone(), &lane)?;
+    rerun_sink.recording_stream.log(
+        "Upgrade/Log",
+        &rerun::TextLog::new(format!(
+            "{} started{}",
+            label,
+            if upgrade_span.is_estimated {
+                " (estimated)"
+            } else {
+                ""
+            }
+        ))
+        .with_level(rerun::TextLogLevel::TRACE),
+    )?;
+    // The research is done at the upgrade event being drawn.
+    rerun_sink.restore_time();
+    rerun_sink.recording_stream.log(entity_path, &lane)?;
+    let research_secs = rerun_sink
+        .summary
+        .game_speed
+        .game_loop_secs(upgrade_span.end_loop - upgrade_span.start_loop);
+    rerun_sink.recording_stream.log(
+        "Upgrade/Log",
+        &rerun::TextLog::new(format!("{} done in {:.0}s", label, research_secs))
+            .with_level(rerun::TextLogLevel::INFO),
+    )?;
+    Ok(())
+}
+
+/// Logs the build order of each player as a markdown table under `BuildOrder/`
+pub fn register_build_orders(rerun_sink: &RerunSink) -> Result<(), SwarmyError> {
+    for build_order in rerun_sink.build_order_extractor.build_orders() {
+        tracing::info!(
+            "Player {} build order: {} steps",
+            build_order.player_id,
+            build_order.steps.len()
+        );
+        rerun_sink.recording_stream.log_static(
+            format!("BuildOrder/{}", build_order.player_id),
+            &rerun::TextDocument::from_markdown(build_order.to_markdown()),
+        )?;
+    }
+    Ok(())
+}
+
+/// Logs the steps of the build order grade as markers on the timeline under `BuildOrderGrade/`,
+/// at the time the player made them, or at the time of the reference for the missed steps.
+/// The seconds each step was off are drawn as a time series.
+pub fn register_build_order_grade(rerun_sink: &RerunSink) -> Result<(), SwarmyError> {
+    let Some(build_order_grade) = &rerun_sink.build_order_grade else {
+        return Ok(());
+    };
+    for step in &build_order_grade.steps {
+        let (game_loop, level) = match (&step.actual, step.status) {
+            (Some(actual), BuildOrderStepStatus::OnTime) => {
+                (actual.game_loop, rerun::TextLogLevel::INFO)
+            }
+            (Some(actual), _) => (actual.game_loop, rerun::TextLogLevel::WARN),
+            (None, _) => (step.reference.game_loop, rerun::TextLogLevel::ERROR),
+        };
+        rerun_sink.set_past_game_loop(game_loop);
+        rerun_sink.recording_stream.log(
+            "BuildOrderGrade/Log",
+            &rerun::TextLog::new(format!(
+                "{:?}: {} (reference {} @{}{})",
+                step.status,
+                step.reference.item,
+                step.reference.game_time(),
+                step.reference.supply,
+                step.delta_secs
+                    .map(|delta_secs| format!(", {:+.0}s", delta_secs))
+                    .unwrap_or_default()
+            ))
+            .with_level(level),
+        )?;
+        if let Some(delta_secs) = step.delta_secs {
+            rerun_sink
+                .recording_stream
+                .log("BuildOrderGrade/Delta", &rerun::Scalars::single(delta_secs))?;
+        }
+    }
+    for step in &build_order_grade.extra_steps {
+        rerun_sink.set_past_game_loop(step.game_loop);
+        rerun_sink.recording_stream.log(
+            "BuildOrderGrade/Log",
+            &rerun::TextLog::new(format!("Extra: {} @{}", step.item, step.supply))
+                .with_level(rerun::TextLogLevel::WARN),
+        )?;
+    }
+    rerun_sink.recording_stream.log_static(
+        "BuildOrderGrade/Report",
+        &rerun::TextDocument::from_markdown(build_order_grade.to_markdown()),
+    )?;
+    rerun_sink.restore_time();
+    Ok(())
+}
+
+/// Dispatches the tracker events to the ReplaySink.
 pub fn add_tracker_event(
     evt: &ReplayTrackerEvent,
     change_hint: UnitChangeHint,
-    recording_stream: &RecordingStream,
+    sink: &mut dyn ReplaySink,
     tracker_loop: i64,
 ) -> Result<(), SwarmyError> {
     match &evt {
         ReplayTrackerEvent::UnitInit(unit_init) => {
-            register_unit_init(unit_init, change_hint, recording_stream, tracker_loop)?;
+            sink.register_unit_init(unit_init, change_hint, tracker_loop)?;
+        }
+        ReplayTrackerEvent::UnitDone(unit_done) => {
+            sink.register_unit_done(unit_done, change_hint, tracker_loop)?;
         }
         ReplayTrackerEvent::UnitBorn(unit_born) => {
-            register_unit_born(unit_born, change_hint, recording_stream, tracker_loop)?;
+            sink.register_unit_born(unit_born, change_hint, tracker_loop)?;
         }
         ReplayTrackerEvent::UnitDied(unit_died) => {
-            register_unit_died(unit_died, change_hint, recording_stream, tracker_loop)?;
+            sink.register_unit_died(unit_died, change_hint, tracker_loop)?;
         }
         ReplayTrackerEvent::UnitPosition(unit_pos) => {
-            register_unit_position(
-                change_hint,
-                unit_pos.clone(),
-                recording_stream,
-                tracker_loop,
-            )?;
+            sink.register_unit_position(change_hint, unit_pos.clone(), tracker_loop)?;
         }
         ReplayTrackerEvent::PlayerStats(player_stats) => {
-            register_player_stats(player_stats, recording_stream)?;
+            sink.register_player_stats(player_stats, tracker_loop)?;
         }
         ReplayTrackerEvent::Upgrade(upgrade) => {
-            recording_stream.log(
-                "Upgrade",
-                &rerun::TextLog::new(format!(
-                    "U:{} [{}@{}]",
-                    upgrade.player_id, upgrade.upgrade_type_name, upgrade.count
-                ))
-                .with_level(rerun::TextLogLevel::TRACE),
-            )?;
+            sink.register_upgrade(upgrade, tracker_loop)?;
         }
         ReplayTrackerEvent::UnitTypeChange(unit_type_change) => {
-            register_unit_type_change(
-                unit_type_change,
-                change_hint,
-                recording_stream,
-                tracker_loop,
-            )?;
+            sink.register_unit_type_change(unit_type_change, change_hint, tracker_loop)?;
         }
         _ => {}
     }
     Ok(())
 }
+
+/// Logs a span as a line at a height, from its start to its end, the line is cleared right after
+/// its end so that it is not joined to the next span of the same entity.
+fn log_span(
+    rerun_sink: &RerunSink,
+    entity_path: &str,
+    height: f64,
+    start_loop: i64,
+    end_loop: i64,
+) -> Result<(), SwarmyError> {
+    let height = rerun::Scalars::single(height);
+    rerun_sink.set_past_game_loop(start_loop);
+    rerun_sink.recording_stream.log(entity_path, &height)?;
+    rerun_sink.set_past_game_loop(end_loop);
+    rerun_sink.recording_stream.log(entity_path, &height)?;
+    rerun_sink.set_past_game_loop(end_loop + 1);
+    rerun_sink
+        .recording_stream
+        .log(entity_path, &rerun::Clear::flat())?;
+    rerun_sink.restore_time();
+    Ok(())
+}
+
+/// Logs the busy and idle spans of each production structure as lines at the height of its lane
+/// under `Production/Structure/{player_id}/{unit_tag}/`, the busy ones in the player color and
+/// the idle ones in gray. The number of busy and idle structures of each player is drawn under
+/// `Production/Busy/` and `Production/Idle/`, and the supply blocks at the supply they happened
+/// under `Production/SupplyBlock/`.
+/// The idle production periods and the supply blocks are logged as warnings in `Production/Log`
+pub fn register_production(rerun_sink: &RerunSink) -> Result<(), SwarmyError> {
+    let production_tracker = &rerun_sink.production_tracker;
+    let game_speed = rerun_sink.summary.game_speed;
+    let mut styled_structures = std::collections::HashSet::new();
+    for span in production_tracker.spans() {
+        let structure_path = format!("Production/Structure/{}/{}", span.player_id, span.unit_tag);
+        if styled_structures.insert(span.unit_tag) {
+            let label = format!("P{} {} {}", span.player_id, span.structure, span.unit_tag);
+            let color = rerun_sink.player_colors.player_color(span.player_id as i64);
+            for (suffix, color) in [("Busy", color), ("Idle", FREYA_GRAY)] {
+                rerun_sink.recording_stream.log_static(
+                    format!("{}/{}", structure_path, suffix),
+                    &rerun::SeriesLines::new()
+                        .with_colors([color])
+                        .with_names([format!("{} {}", label, suffix)])
+                        .with_widths([4.]),
+                )?;
+            }
+        }
+        let suffix = if span.is_busy { "Busy" } else { "Idle" };
+        log_span(
+            rerun_sink,
+            &format!("{}/{}", structure_path, suffix),
+            span.lane as f64,
+            span.start_loop,
+            span.end_loop,
+        )?;
+    }
+    for span in production_tracker.idle_periods() {
+        rerun_sink.set_past_game_loop(span.start_loop);
+        rerun_sink.recording_stream.log(
+            "Production/Log",
+            &rerun::TextLog::new(format!(
+                "P{} {} {} idle for {:.0}s",
+                span.player_id,
+                span.structure,
+                span.unit_tag,
+                game_speed.game_loop_secs(span.end_loop - span.start_loop)
+            ))
+            .with_level(rerun::TextLogLevel::WARN),
+        )?;
+    }
+    for player in &rerun_sink.summary.players {
+        let color = rerun_sink
+            .player_colors
+            .player_color(player.player_id as i64);
+        for group in ["Busy", "Idle", "SupplyBlock"] {
+            rerun_sink.recording_stream.log_static(
+                format!("Production/{}/{}", group, player.player_id),
+                &rerun::SeriesLines::new()
+                    .with_colors([color])
+                    .with_names([player.name.clone()]),
+            )?;
+        }
+        // The counts hold until they change, the previous count is logged right before the
+        // change so that the line is drawn in steps.
+        let mut previous = (0, 0);
+        for (game_loop, busy, idle) in production_tracker.player_counts(player.player_id) {
+            if game_loop > 0 {
+                rerun_sink.set_past_game_loop(game_loop - 1);
+                log_production_counts(rerun_sink, player.player_id, previous)?;
+            }
+            rerun_sink.set_past_game_loop(game_loop);
+            log_production_counts(rerun_sink, player.player_id, (busy, idle))?;
+            previous = (busy, idle);
+        }
+    }
+    for supply_block in production_tracker.supply_blocks() {
+        log_span(
+            rerun_sink,
+            &format!("Production/SupplyBlock/{}", supply_block.player_id),
+            supply_block.supply as f64,
+            supply_block.start_loop,
+            supply_block.end_loop,
+        )?;
+        rerun_sink.set_past_game_loop(supply_block.start_loop);
+        rerun_sink.recording_stream.log(
+            "Production/Log",
+            &rerun::TextLog::new(format!(
+                "P{} supply blocked at {} for {:.0}s",
+                supply_block.player_id,
+                supply_block.supply,
+                game_speed.game_loop_secs(supply_block.end_loop - supply_block.start_loop)
+            ))
+            .with_level(rerun::TextLogLevel::WARN),
+        )?;
+    }
+    for summary in production_tracker.summaries() {
+        tracing::info!(
+            "Player {} production: {:.0}s busy, {:.0}s idle, {} idle periods, {} supply blocks",
+            summary.player_id,
+            summary.busy_secs,
+            summary.idle_secs,
+            summary.idle_periods,
+            summary.supply_blocks
+        );
+    }
+    rerun_sink.recording_stream.log_static(
+        "Production/Summary",
+        &rerun::TextDocument::from_markdown(production_tracker.to_markdown()),
+    )?;
+    rerun_sink.restore_time();
+    Ok(())
+}
+
+/// Logs the number of busy and idle production structures of a player.
+fn log_production_counts(
+    rerun_sink: &RerunSink,
+    player_id: u8,
+    (busy, idle): (usize, usize),
+) -> Result<(), SwarmyError> {
+    rerun_sink.recording_stream.log(
+        format!("Production/Busy/{}", player_id),
+        &rerun::Scalars::single(busy as f64),
+    )?;
+    rerun_sink.recording_stream.log(
+        format!("Production/Idle/{}", player_id),
+        &rerun::Scalars::single(idle as f64),
+    )?;
+    Ok(())
+}
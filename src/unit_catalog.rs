@@ -0,0 +1,133 @@
+//! Unit catalog, the size, color, category, race, cost, sight and build time of the units by name.
+//! A default catalog is bundled in `assets/unit_catalog.json`, a different file can be loaded to
+//! override its entries, for example:
+//! ```json
+//! {
+//!   "units": {
+//!     "Marine": {"radius": 0.4, "category": "Army", "race": "Terran", "minerals": 50, "supply": 1, "sight": 9, "build_secs": 18}
+//!   },
+//!   "team_overrides": {
+//!     "1": {"Marine": {"color": [255, 0, 0, 255]}}
+//!   }
+//! }
+//! ```
+//! Entries without a color are drawn with the color of the player that owns the unit.
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The bundled catalog, covering the melee units, resources and common map features.
+const BUNDLED_UNIT_CATALOG: &str = include_str!("../assets/unit_catalog.json");
+
+/// The kind of thing a unit is.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UnitCategory {
+    Worker,
+    Army,
+    Structure,
+    Resource,
+    #[default]
+    Neutral,
+}
+
+/// The race a unit belongs to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UnitRace {
+    Terran,
+    Zerg,
+    Protoss,
+    #[default]
+    Neutral,
+}
+
+/// The properties of a unit type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitCatalogEntry {
+    /// The radius of the footprint of the unit.
+    pub radius: f32,
+    /// The category of the unit.
+    pub category: UnitCategory,
+    /// The race of the unit.
+    pub race: UnitRace,
+    /// The minerals spent on the unit, including the units it morphed from.
+    #[serde(default)]
+    pub minerals: u32,
+    /// The gas spent on the unit, including the units it morphed from.
+    #[serde(default)]
+    pub gas: u32,
+    /// The supply the unit takes.
+    #[serde(default)]
+    pub supply: f32,
+    /// The sight radius of the unit, 0 for units that do not grant vision.
+    #[serde(default)]
+    pub sight: f32,
+    /// The seconds it takes to make the unit at Faster speed, 0 when unknown. Morphs count only
+    /// the morph itself.
+    #[serde(default)]
+    pub build_secs: f32,
+    /// The color of the unit, the player color is used when missing.
+    #[serde(default)]
+    pub color: Option<[u8; 4]>,
+}
+
+/// Per-team changes to a catalog entry.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitCatalogOverride {
+    #[serde(default)]
+    pub radius: Option<f32>,
+    #[serde(default)]
+    pub color: Option<[u8; 4]>,
+}
+
+/// The unit types known to swarmy.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitCatalog {
+    /// The catalog entries by unit name.
+    #[serde(default)]
+    pub units: HashMap<String, UnitCatalogEntry>,
+    /// The overrides by team id and unit name.
+    #[serde(default)]
+    pub team_overrides: HashMap<u8, HashMap<String, UnitCatalogOverride>>,
+}
+
+impl UnitCatalog {
+    /// Returns the catalog bundled with swarmy.
+    pub fn bundled() -> Self {
+        serde_json::from_str(BUNDLED_UNIT_CATALOG).expect("The bundled unit catalog is valid")
+    }
+
+    /// Reads a catalog from a JSON file.
+    pub fn from_file(path: &str) -> Result<Self, SwarmyError> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Adds the entries of another catalog, replacing the existing ones with the same name.
+    pub fn extend(&mut self, other: UnitCatalog) {
+        self.units.extend(other.units);
+        for (team_id, overrides) in other.team_overrides {
+            self.team_overrides
+                .entry(team_id)
+                .or_default()
+                .extend(overrides);
+        }
+    }
+
+    /// Returns the entry of a unit, with the overrides of its team applied.
+    pub fn get(&self, unit_name: &str, team_id: Option<u8>) -> Option<UnitCatalogEntry> {
+        let mut entry = self.units.get(unit_name)?.clone();
+        if let Some(team_override) = team_id
+            .and_then(|team_id| self.team_overrides.get(&team_id))
+            .and_then(|overrides| overrides.get(unit_name))
+        {
+            if let Some(radius) = team_override.radius {
+                entry.radius = radius;
+            }
+            if team_override.color.is_some() {
+                entry.color = team_override.color;
+            }
+        }
+        Some(entry)
+    }
+}
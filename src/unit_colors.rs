@@ -1,77 +1,83 @@
 use super::*;
-// Returns the expected size of units depending on their type
-pub fn get_unit_sized_color(unit_name: &str, user_id: i64) -> (f32, [u8; 4]) {
-    let mut unit_size = 0.45;
-    let color = match unit_name {
-        "VespeneGeyser" => FREYA_LIGHT_GREEN,
-        "SpacePlatformGeyser" => FREYA_LIGHT_GREEN,
-        "LabMineralField" => {
-            unit_size = 0.24;
-            FREYA_LIGHT_BLUE
-        }
-        "LabMineralField750" => {
-            unit_size = 0.36;
-            FREYA_LIGHT_BLUE
-        }
-        "MineralField" => {
-            unit_size = 0.48;
-            FREYA_LIGHT_BLUE
-        }
-        "MineralField450" => {
-            unit_size = 0.6;
-            FREYA_LIGHT_BLUE
-        }
-        "MineralField750" => {
-            unit_size = 0.72;
-            FREYA_LIGHT_BLUE
-        }
-        "XelNagaTower" => {
-            // This should be super transparent
-            unit_size = 0.72;
-            FREYA_WHITE
-        }
-        "RichMineralField" => FREYA_GOLD,
-        "RichMineralField750" => FREYA_ORANGE,
-        "DestructibleRockEx1DiagonalHugeBLUR" => {
-            unit_size = 2.0;
-            FREYA_GRAY
-        }
-        "DestructibleDebris6x6" => {
-            unit_size = 1.8;
-            FREYA_GRAY
-        }
-        "UnbuildablePlatesDestructible" => {
-            unit_size = 0.6;
-            FREYA_LIGHT_GRAY
-        }
-        "Overlord" => {
-            unit_size = 0.6;
-            FREYA_YELLOW
-        }
-        "SCV" | "Drone" | "Probe" | "Larva" => {
-            unit_size = 0.3;
-            FREYA_LIGHT_GRAY
-        }
-        "Hatchery" | "CommandCenter" | "Nexus" => {
-            unit_size = 1.2;
-            FREYA_PINK
-        }
-        "Broodling" => {
-            unit_size = 0.06;
-            FREYA_LIGHT_GRAY
-        }
-        _ => {
-            // Ignore the Beacons for now.
-            if !unit_name.starts_with("Beacon") {
-                tracing::warn!("Unknown unit name: '{}'", unit_name);
+use std::collections::HashMap;
+
+/// The in-game colors of the players, read from the replay details.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerColors {
+    /// The colors by the player id of the tracker events.
+    by_player_id: HashMap<u8, [u8; 4]>,
+    /// The colors by the user id of the game events.
+    by_user_id: HashMap<i64, [u8; 4]>,
+}
+
+impl PlayerColors {
+    pub fn new(summary: &ReplaySummary) -> Self {
+        let mut res = Self::default();
+        for player in &summary.players {
+            res.by_player_id.insert(player.player_id, player.color);
+            if let Some(user_id) = player.user_id {
+                res.by_user_id.insert(user_id, player.color);
             }
-            // Fallback to user color
-            user_color(user_id)
         }
-    };
-    (unit_size, color)
+        res
+    }
+
+    /// Returns the color of a tracker event player id, falls back to the Freya palette.
+    pub fn player_color(&self, player_id: i64) -> [u8; 4] {
+        u8::try_from(player_id)
+            .ok()
+            .and_then(|player_id| self.by_player_id.get(&player_id))
+            .copied()
+            .unwrap_or_else(|| user_color(player_id))
+    }
+
+    /// Returns the color of a game event user id, falls back to the Freya palette.
+    pub fn user_color(&self, user_id: i64) -> [u8; 4] {
+        self.by_user_id
+            .get(&user_id)
+            .copied()
+            .unwrap_or_else(|| user_color(user_id))
+    }
+}
+
+/// The size of the units that are not in the catalog.
+pub const DEFAULT_UNIT_RADIUS: f32 = 0.45;
+
+// Returns the expected size of units depending on their type
+// The player_id is the owner of the unit as seen by the tracker events, its team_id is used to
+// apply the per-team catalog overrides.
+pub fn get_unit_sized_color(
+    unit_name: &str,
+    player_id: i64,
+    team_id: Option<u8>,
+    player_colors: &PlayerColors,
+    unit_catalog: &UnitCatalog,
+) -> (f32, [u8; 4]) {
+    match unit_catalog.get(unit_name, team_id) {
+        Some(entry) => (
+            entry.radius,
+            entry
+                .color
+                .unwrap_or_else(|| player_colors.player_color(player_id)),
+        ),
+        None => {
+            tracing::debug!("Unknown unit name: '{}'", unit_name);
+            // Fallback to the player color
+            (DEFAULT_UNIT_RADIUS, player_colors.player_color(player_id))
+        }
+    }
+}
+
+/// Classifies a unit by its name, units that are not in the catalog are considered Neutral.
+pub fn get_unit_category(unit_name: &str, unit_catalog: &UnitCatalog) -> UnitCategory {
+    unit_catalog
+        .units
+        .get(unit_name)
+        .map(|entry| entry.category)
+        .unwrap_or_default()
 }
 
+/// A fixed palette for when the replay does not provide the player colors.
 pub fn user_color(user_id: i64) -> [u8; 4] {
     match user_id {
         0 => FREYA_LIGHT_GREEN,
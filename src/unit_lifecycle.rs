@@ -0,0 +1,37 @@
+//! Unit lifecycle tracking, the entity paths logged for each alive unit.
+//! When a unit dies every one of its entities is cleared, so that no position, arrow or trail
+//! lingers in the viewer.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// The entity paths logged for the alive units.
+#[derive(Debug, Default, Clone)]
+pub struct UnitLifecycle {
+    /// The entity paths by unit tag.
+    entity_paths: HashMap<i64, BTreeSet<String>>,
+}
+
+impl UnitLifecycle {
+    /// Records that an entity path was logged for a unit.
+    pub fn register(&mut self, unit_tag: i64, entity_path: &str) {
+        let entity_paths = self.entity_paths.entry(unit_tag).or_default();
+        if !entity_paths.contains(entity_path) {
+            entity_paths.insert(entity_path.to_string());
+        }
+    }
+
+    /// Forgets a unit, returns the entity paths that were logged for it so they can be cleared.
+    pub fn unregister(&mut self, unit_tag: i64) -> BTreeSet<String> {
+        self.entity_paths.remove(&unit_tag).unwrap_or_default()
+    }
+
+    /// The entity paths logged for a unit.
+    pub fn entity_paths(&self, unit_tag: i64) -> Option<&BTreeSet<String>> {
+        self.entity_paths.get(&unit_tag)
+    }
+
+    /// The tags of the units that have entities in the viewer.
+    pub fn unit_tags(&self) -> impl Iterator<Item = &i64> {
+        self.entity_paths.keys()
+    }
+}
@@ -0,0 +1,412 @@
+//! Upgrade timelines, when each player started and finished researching their upgrades.
+//! The tracker events only report when an upgrade is done, its start is taken from the research
+//! command the player issued before. The ability names are not always decoded with the same
+//! protocol as the replay, so when no research command matches the upgrade, its start is
+//! estimated from its research time at Faster speed.
+//!
+//! The UpgradeTimeline is a ReplaySink on its own, so it can be used for post-game reports:
+//! ```no_run
+//! use swarmy::*;
+//! let sc2_rerun = SC2Rerun::new("assets/Burrow.SC2Replay", Default::default()).unwrap();
+//! let mut upgrade_timeline = UpgradeTimeline::default();
+//! sc2_rerun.add_events(&mut upgrade_timeline).unwrap();
+//! for upgrade_span in upgrade_timeline.spans() {
+//!     println!("{:?}", upgrade_span);
+//! }
+//! ```
+
+use super::*;
+use std::collections::HashMap;
+
+/// The research time in seconds at Faster speed of the upgrades, by their tracker event name.
+const RESEARCH_SECS: [(&str, f64); 87] = [
+    // Terran
+    ("TerranInfantryWeaponsLevel1", 114.),
+    ("TerranInfantryWeaponsLevel2", 136.),
+    ("TerranInfantryWeaponsLevel3", 157.),
+    ("TerranInfantryArmorsLevel1", 114.),
+    ("TerranInfantryArmorsLevel2", 136.),
+    ("TerranInfantryArmorsLevel3", 157.),
+    ("TerranVehicleWeaponsLevel1", 114.),
+    ("TerranVehicleWeaponsLevel2", 136.),
+    ("TerranVehicleWeaponsLevel3", 157.),
+    ("TerranShipWeaponsLevel1", 114.),
+    ("TerranShipWeaponsLevel2", 136.),
+    ("TerranShipWeaponsLevel3", 157.),
+    ("TerranVehicleAndShipArmorsLevel1", 114.),
+    ("TerranVehicleAndShipArmorsLevel2", 136.),
+    ("TerranVehicleAndShipArmorsLevel3", 157.),
+    ("Stimpack", 100.),
+    ("ShieldWall", 79.),
+    ("PunisherGrenades", 43.),
+    ("PersonalCloaking", 86.),
+    ("BansheeCloak", 86.),
+    ("BansheeSpeed", 93.),
+    ("HiSecAutoTracking", 57.),
+    ("TerranBuildingArmor", 100.),
+    ("DrillClaws", 79.),
+    ("SmartServos", 79.),
+    ("HighCapacityBarrels", 79.),
+    ("LiberatorAGRangeUpgrade", 79.),
+    ("MedivacCaduceusReactor", 50.),
+    ("EnhancedShockwaves", 79.),
+    ("CycloneLockOnDamageUpgrade", 100.),
+    // Protoss
+    ("WarpGateResearch", 100.),
+    ("Charge", 100.),
+    ("BlinkTech", 121.),
+    ("AdeptPiercingAttack", 100.),
+    ("PsiStormTech", 79.),
+    ("ExtendedThermalLance", 100.),
+    ("ObserverGraviticBooster", 57.),
+    ("GraviticDrive", 57.),
+    ("PhoenixRangeUpgrade", 64.),
+    ("VoidRaySpeedUpgrade", 57.),
+    ("TempestGroundAttackUpgrade", 79.),
+    ("DarkTemplarBlinkUpgrade", 121.),
+    ("ProtossGroundWeaponsLevel1", 129.),
+    ("ProtossGroundWeaponsLevel2", 154.),
+    ("ProtossGroundWeaponsLevel3", 179.),
+    ("ProtossGroundArmorsLevel1", 129.),
+    ("ProtossGroundArmorsLevel2", 154.),
+    ("ProtossGroundArmorsLevel3", 179.),
+    ("ProtossShieldsLevel1", 129.),
+    ("ProtossShieldsLevel2", 154.),
+    ("ProtossShieldsLevel3", 179.),
+    ("ProtossAirWeaponsLevel1", 129.),
+    ("ProtossAirWeaponsLevel2", 154.),
+    ("ProtossAirWeaponsLevel3", 179.),
+    ("ProtossAirArmorsLevel1", 129.),
+    ("ProtossAirArmorsLevel2", 154.),
+    ("ProtossAirArmorsLevel3", 179.),
+    // Zerg
+    ("zerglingmovementspeed", 79.),
+    ("zerglingattackspeed", 93.),
+    ("Burrow", 71.),
+    ("overlordspeed", 43.),
+    ("CentrificalHooks", 71.),
+    ("GlialReconstitution", 79.),
+    ("TunnelingClaws", 79.),
+    ("EvolveGroovedSpines", 50.),
+    ("EvolveMuscularAugments", 64.),
+    ("NeuralParasite", 79.),
+    ("InfestorEnergyUpgrade", 57.),
+    ("ChitinousPlating", 79.),
+    ("AnabolicSynthesis", 43.),
+    ("DiggingClaws", 57.),
+    ("LurkerRange", 57.),
+    ("ZergMeleeWeaponsLevel1", 114.),
+    ("ZergMeleeWeaponsLevel2", 136.),
+    ("ZergMeleeWeaponsLevel3", 157.),
+    ("ZergMissileWeaponsLevel1", 114.),
+    ("ZergMissileWeaponsLevel2", 136.),
+    ("ZergMissileWeaponsLevel3", 157.),
+    ("ZergGroundArmorsLevel1", 114.),
+    ("ZergGroundArmorsLevel2", 136.),
+    ("ZergGroundArmorsLevel3", 157.),
+    ("ZergFlyerWeaponsLevel1", 114.),
+    ("ZergFlyerWeaponsLevel2", 136.),
+    ("ZergFlyerWeaponsLevel3", 157.),
+    ("ZergFlyerArmorsLevel1", 114.),
+    ("ZergFlyerArmorsLevel2", 136.),
+    ("ZergFlyerArmorsLevel3", 157.),
+];
+
+/// The structures that research the upgrades, by the prefix of their tracker event name.
+const RESEARCH_STRUCTURES: [(&str, &[&str]); 51] = [
+    // Terran
+    ("TerranInfantry", &["EngineeringBay"]),
+    ("TerranBuildingArmor", &["EngineeringBay"]),
+    ("HiSecAutoTracking", &["EngineeringBay"]),
+    ("TerranVehicle", &["Armory"]),
+    ("TerranShip", &["Armory"]),
+    ("Stimpack", &["BarracksTechLab"]),
+    ("ShieldWall", &["BarracksTechLab"]),
+    ("PunisherGrenades", &["BarracksTechLab"]),
+    ("DrillClaws", &["FactoryTechLab"]),
+    ("SmartServos", &["FactoryTechLab"]),
+    ("HighCapacityBarrels", &["FactoryTechLab"]),
+    ("CycloneLockOnDamageUpgrade", &["FactoryTechLab"]),
+    ("BansheeCloak", &["StarportTechLab"]),
+    ("BansheeSpeed", &["StarportTechLab"]),
+    ("PersonalCloaking", &["GhostAcademy"]),
+    ("EnhancedShockwaves", &["GhostAcademy"]),
+    ("LiberatorAGRangeUpgrade", &["FusionCore"]),
+    ("MedivacCaduceusReactor", &["FusionCore"]),
+    // Protoss
+    ("ProtossGround", &["Forge"]),
+    ("ProtossShields", &["Forge"]),
+    ("ProtossAir", &["CyberneticsCore"]),
+    ("WarpGateResearch", &["CyberneticsCore"]),
+    ("Charge", &["TwilightCouncil"]),
+    ("BlinkTech", &["TwilightCouncil"]),
+    ("AdeptPiercingAttack", &["TwilightCouncil"]),
+    ("PsiStormTech", &["TemplarArchive"]),
+    ("ExtendedThermalLance", &["RoboticsBay"]),
+    ("ObserverGraviticBooster", &["RoboticsBay"]),
+    ("GraviticDrive", &["RoboticsBay"]),
+    ("PhoenixRangeUpgrade", &["FleetBeacon"]),
+    ("VoidRaySpeedUpgrade", &["FleetBeacon"]),
+    ("TempestGroundAttackUpgrade", &["FleetBeacon"]),
+    ("DarkTemplarBlinkUpgrade", &["DarkShrine"]),
+    // Zerg
+    ("ZergMelee", &["EvolutionChamber"]),
+    ("ZergMissile", &["EvolutionChamber"]),
+    ("ZergGroundArmors", &["EvolutionChamber"]),
+    ("ZergFlyer", &["Spire", "GreaterSpire"]),
+    ("zergling", &["SpawningPool"]),
+    ("Burrow", &["Hatchery", "Lair", "Hive"]),
+    ("overlordspeed", &["Hatchery", "Lair", "Hive"]),
+    ("CentrificalHooks", &["BanelingNest"]),
+    ("GlialReconstitution", &["RoachWarren"]),
+    ("TunnelingClaws", &["RoachWarren"]),
+    ("EvolveGroovedSpines", &["HydraliskDen"]),
+    ("EvolveMuscularAugments", &["HydraliskDen"]),
+    ("NeuralParasite", &["InfestationPit"]),
+    ("InfestorEnergyUpgrade", &["InfestationPit"]),
+    ("ChitinousPlating", &["UltraliskCavern"]),
+    ("AnabolicSynthesis", &["UltraliskCavern"]),
+    ("DiggingClaws", &["LurkerDenMP"]),
+    ("LurkerRange", &["LurkerDenMP"]),
+];
+
+/// Returns the types of the structures that research an upgrade, empty when unknown.
+pub fn research_structures(upgrade_name: &str) -> &'static [&'static str] {
+    RESEARCH_STRUCTURES
+        .iter()
+        .find(|(prefix, _)| upgrade_name.starts_with(prefix))
+        .map(|(_, structures)| *structures)
+        .unwrap_or_default()
+}
+
+/// Whether a structure type researches upgrades.
+pub fn is_research_structure(structure_name: &str) -> bool {
+    RESEARCH_STRUCTURES
+        .iter()
+        .any(|(_, structures)| structures.contains(&structure_name))
+}
+
+/// Returns the research time in seconds at Faster speed of an upgrade, None when unknown.
+fn research_secs(upgrade_name: &str) -> Option<f64> {
+    RESEARCH_SECS
+        .iter()
+        .find(|(name, _)| *name == upgrade_name)
+        .map(|(_, secs)| *secs)
+}
+
+/// Returns the research time in game loops of an upgrade, the times are given at Faster speed.
+fn research_loops(upgrade_name: &str) -> Option<i64> {
+    research_secs(upgrade_name).map(|secs| (secs * FASTER_GAME_LOOPS_PER_SECOND) as i64)
+}
+
+/// Lowercases a name and drops anything that is not alphanumeric, so that ability and upgrade
+/// names can be compared.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// The research of an upgrade by a player.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpgradeSpan {
+    pub player_id: u8,
+    /// The tracker event name of the upgrade, i.e. `ZergMissileWeaponsLevel1`
+    pub name: String,
+    /// The game loop the research started at.
+    pub start_loop: i64,
+    /// The game loop the research was done at.
+    pub end_loop: i64,
+    /// Whether the start is estimated from the research time rather than a research command.
+    pub is_estimated: bool,
+    /// The row of the upgrade in the timeline, the same upgrade of every player shares a row so
+    /// that they are side by side.
+    pub lane: usize,
+}
+
+/// Collects the research spans of the upgrades of each player.
+#[derive(Debug, Clone, Default)]
+pub struct UpgradeTimeline {
+    /// The replay metadata, to map the game events user ids to player ids.
+    summary: ReplaySummary,
+    /// The research commands not matched to an upgrade yet, the game loop and the normalized
+    /// ability name by player id.
+    research_cmds: HashMap<u8, Vec<(i64, String)>>,
+    /// The row of each upgrade, by upgrade name.
+    lanes: HashMap<String, usize>,
+    /// The upgrades done, in order.
+    spans: Vec<UpgradeSpan>,
+}
+
+impl UpgradeTimeline {
+    /// The upgrades done so far, in the order they were done.
+    pub fn spans(&self) -> &[UpgradeSpan] {
+        &self.spans
+    }
+
+    /// Adds a command of a player, only the research commands are kept.
+    pub fn add_cmd(&mut self, player_id: u8, ability: &str, game_loop: i64) {
+        if ability.contains("Research") {
+            self.research_cmds
+                .entry(player_id)
+                .or_default()
+                .push((game_loop, normalize(ability)));
+        }
+    }
+
+    /// Adds an upgrade done by a player, its start is the latest research command that names it
+    /// within twice its research time, so that a research cancelled and queued again starts
+    /// when it was queued again, or else its estimated start.
+    /// Returns the research span, None for cosmetic upgrades or the ones the game starts with.
+    pub fn add_upgrade(
+        &mut self,
+        player_id: u8,
+        upgrade_name: &str,
+        game_loop: i64,
+    ) -> Option<UpgradeSpan> {
+        if game_loop == 0
+            || COSMETIC_UPGRADE_PREFIXES
+                .iter()
+                .any(|prefix| upgrade_name.starts_with(prefix))
+        {
+            return None;
+        }
+        let research_loops = research_loops(upgrade_name);
+        let normalized_name = normalize(upgrade_name);
+        let research_cmds = self.research_cmds.entry(player_id).or_default();
+        let research_cmd = research_cmds
+            .iter()
+            .filter(|(cmd_loop, ability)| {
+                ability.contains(&normalized_name)
+                    && research_loops
+                        .is_none_or(|research_loops| game_loop - cmd_loop <= 2 * research_loops)
+            })
+            .map(|(cmd_loop, _)| *cmd_loop)
+            .max();
+        // The commands naming this upgrade are used up, i.e. the cancelled research.
+        research_cmds.retain(|(_, ability)| !ability.contains(&normalized_name));
+        let (start_loop, is_estimated) = match (research_cmd, research_loops) {
+            (Some(cmd_loop), _) => (cmd_loop, false),
+            (None, Some(research_loops)) => ((game_loop - research_loops).max(0), true),
+            (None, None) => (game_loop, true),
+        };
+        let next_lane = self.lanes.len();
+        let lane = *self
+            .lanes
+            .entry(upgrade_name.to_string())
+            .or_insert(next_lane);
+        let upgrade_span = UpgradeSpan {
+            player_id,
+            name: upgrade_name.to_string(),
+            start_loop,
+            end_loop: game_loop,
+            is_estimated,
+            lane,
+        };
+        self.spans.push(upgrade_span.clone());
+        Some(upgrade_span)
+    }
+}
+
+impl ReplaySink for UpgradeTimeline {
+    fn register_summary(&mut self, summary: &ReplaySummary) -> Result<(), SwarmyError> {
+        self.summary = summary.clone();
+        Ok(())
+    }
+
+    fn register_upgrade(
+        &mut self,
+        upgrade: &UpgradeEvent,
+        tracker_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        self.add_upgrade(upgrade.player_id, &upgrade.upgrade_type_name, tracker_loop);
+        Ok(())
+    }
+
+    fn register_cmd(
+        &mut self,
+        user_id: i64,
+        _change_hint: UnitChangeHint,
+        game_cmd: &GameSCmdEvent,
+        game_loop: i64,
+    ) -> Result<(), SwarmyError> {
+        if let (Some(abil), Some(player_id)) =
+            (&game_cmd.m_abil, self.summary.user_player_id(user_id))
+        {
+            self.add_cmd(player_id, &abil.ability, game_loop);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_the_research_times_at_faster_speed() {
+        // 100 and 129 seconds at 22.4 game loops per second.
+        assert_eq!(research_loops("Stimpack"), Some(2240));
+        assert_eq!(research_loops("ProtossGroundWeaponsLevel1"), Some(2889));
+        assert_eq!(research_loops("UnknownUpgrade"), None);
+    }
+
+    #[test]
+    fn it_starts_the_upgrade_at_its_research_command() {
+        let mut upgrade_timeline = UpgradeTimeline::default();
+        upgrade_timeline.add_cmd(1, "BarracksTechLabResearch/Stimpack", 1000);
+        upgrade_timeline.add_cmd(2, "BarracksTechLabResearch/Stimpack", 1500);
+        upgrade_timeline.add_cmd(1, "BarracksTrain/Marine", 1100);
+        let upgrade_span = upgrade_timeline.add_upgrade(1, "Stimpack", 3300).unwrap();
+        assert_eq!(
+            upgrade_span,
+            UpgradeSpan {
+                player_id: 1,
+                name: "Stimpack".to_string(),
+                start_loop: 1000,
+                end_loop: 3300,
+                is_estimated: false,
+                lane: 0,
+            }
+        );
+        // The command of the other player is kept for their own upgrade, in the same lane.
+        let upgrade_span = upgrade_timeline.add_upgrade(2, "Stimpack", 3800).unwrap();
+        assert_eq!((upgrade_span.start_loop, upgrade_span.lane), (1500, 0));
+        assert_eq!(upgrade_timeline.spans().len(), 2);
+    }
+
+    #[test]
+    fn it_starts_a_research_queued_again_at_its_last_command() {
+        let mut upgrade_timeline = UpgradeTimeline::default();
+        upgrade_timeline.add_cmd(1, "BarracksTechLabResearch/Stimpack", 500);
+        upgrade_timeline.add_cmd(1, "BarracksTechLabResearch/Stimpack", 1000);
+        let upgrade_span = upgrade_timeline.add_upgrade(1, "Stimpack", 3240).unwrap();
+        assert_eq!(upgrade_span.start_loop, 1000);
+        assert!(!upgrade_span.is_estimated);
+    }
+
+    #[test]
+    fn it_estimates_the_start_without_a_research_command() {
+        let mut upgrade_timeline = UpgradeTimeline::default();
+        // A command older than twice the research time is not the start of the upgrade.
+        upgrade_timeline.add_cmd(1, "ForgeResearch/ProtossGroundWeaponsLevel1", 100);
+        let upgrade_span = upgrade_timeline
+            .add_upgrade(1, "ProtossGroundWeaponsLevel1", 10000)
+            .unwrap();
+        assert_eq!(
+            (upgrade_span.start_loop, upgrade_span.is_estimated),
+            (10000 - 2889, true)
+        );
+        // Without a research time the span is empty.
+        let upgrade_span = upgrade_timeline
+            .add_upgrade(1, "UnknownUpgrade", 12000)
+            .unwrap();
+        assert_eq!(
+            (upgrade_span.start_loop, upgrade_span.is_estimated),
+            (12000, true)
+        );
+        // The upgrades the game starts with are not researched.
+        assert!(upgrade_timeline.add_upgrade(1, "Stimpack", 0).is_none());
+    }
+}
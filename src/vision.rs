@@ -0,0 +1,278 @@
+//! Vision, the areas of the map each player can see and has ever scouted.
+//! The map is split in square cells, a cell is visible to a player when it is within the sight
+//! radius of one of their units. The area around the camera of the player is tracked as well,
+//! it does not grant vision but shows where the player is looking at.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// The area around the camera target a player is looking at, in map units.
+pub const CAMERA_HALF_SIZE: (f32, f32) = (5.0, 5.0);
+
+/// A unit that grants vision to its owner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct UnitSight {
+    player_id: u8,
+    pos: (f32, f32),
+    sight: f32,
+}
+
+/// The vision grids of the players.
+#[derive(Debug, Clone)]
+pub struct VisionMap {
+    /// The size of a side of a cell in map units.
+    cell_size: f32,
+    /// The number of cells horizontally.
+    columns: usize,
+    /// The number of cells vertically.
+    rows: usize,
+    /// The units that grant vision, by unit tag.
+    units: HashMap<i64, UnitSight>,
+    /// The camera target of the players, by player id.
+    cameras: HashMap<u8, (f32, f32)>,
+    /// The cells each player has ever seen, by player id.
+    scouted: HashMap<u8, Vec<bool>>,
+    /// The players whose vision changed since they were last updated.
+    changed_players: BTreeSet<u8>,
+    /// The game loop the changed players were last taken at.
+    update_loop: i64,
+}
+
+impl Default for VisionMap {
+    fn default() -> Self {
+        Self::new((256, 256), 4.)
+    }
+}
+
+impl VisionMap {
+    pub fn new(map_size: (u32, u32), cell_size: f32) -> Self {
+        let cell_size = cell_size.max(1.);
+        Self {
+            cell_size,
+            columns: ((map_size.0 as f32 / cell_size).ceil() as usize).max(1),
+            rows: ((map_size.1 as f32 / cell_size).ceil() as usize).max(1),
+            units: HashMap::new(),
+            cameras: HashMap::new(),
+            scouted: HashMap::new(),
+            changed_players: BTreeSet::new(),
+            update_loop: 0,
+        }
+    }
+
+    /// The width and height of the grids in cells.
+    pub fn resolution(&self) -> [u32; 2] {
+        [self.columns as u32, self.rows as u32]
+    }
+
+    /// Sets where a unit is and how far it sees, units with no sight are forgotten.
+    pub fn set_unit(&mut self, unit_tag: i64, player_id: u8, pos: (f32, f32), sight: f32) {
+        if sight <= 0. {
+            self.remove_unit(unit_tag);
+            return;
+        }
+        let unit_sight = UnitSight {
+            player_id,
+            pos,
+            sight,
+        };
+        if let Some(previous) = self.units.insert(unit_tag, unit_sight) {
+            if previous == unit_sight {
+                return;
+            }
+            self.changed_players.insert(previous.player_id);
+        }
+        self.changed_players.insert(player_id);
+    }
+
+    /// Forgets a unit, i.e. when it dies.
+    pub fn remove_unit(&mut self, unit_tag: i64) {
+        if let Some(previous) = self.units.remove(&unit_tag) {
+            self.changed_players.insert(previous.player_id);
+        }
+    }
+
+    /// Sets where the camera of a player is looking at.
+    pub fn set_camera(&mut self, player_id: u8, center: (f32, f32)) {
+        if self.cameras.insert(player_id, center) != Some(center) {
+            self.changed_players.insert(player_id);
+        }
+    }
+
+    /// Returns the players whose vision changed since this last returned them, at most once every
+    /// step of game loops so that the grids are not redrawn on every event.
+    pub fn take_changed_players(&mut self, game_loop: i64, step_loops: i64) -> BTreeSet<u8> {
+        if game_loop < self.update_loop + step_loops {
+            return BTreeSet::new();
+        }
+        self.update_loop = game_loop;
+        std::mem::take(&mut self.changed_players)
+    }
+
+    /// Returns the cell of a map position, clamped to the grid.
+    fn cell(&self, pos: (f32, f32)) -> (usize, usize) {
+        (
+            ((pos.0 / self.cell_size).max(0.) as usize).min(self.columns - 1),
+            ((pos.1 / self.cell_size).max(0.) as usize).min(self.rows - 1),
+        )
+    }
+
+    /// Returns the cells currently visible to a player and the cells around their camera.
+    fn visible_cells(&self, player_id: u8) -> (Vec<bool>, Vec<bool>) {
+        let mut visible = vec![false; self.columns * self.rows];
+        for unit in self
+            .units
+            .values()
+            .filter(|unit| unit.player_id == player_id)
+        {
+            let (min_column, min_row) =
+                self.cell((unit.pos.0 - unit.sight, unit.pos.1 - unit.sight));
+            let (max_column, max_row) =
+                self.cell((unit.pos.0 + unit.sight, unit.pos.1 + unit.sight));
+            for row in min_row..=max_row {
+                for column in min_column..=max_column {
+                    // The distance from the unit to the center of the cell.
+                    let x = (column as f32 + 0.5) * self.cell_size - unit.pos.0;
+                    let y = (row as f32 + 0.5) * self.cell_size - unit.pos.1;
+                    if x * x + y * y <= unit.sight * unit.sight {
+                        visible[row * self.columns + column] = true;
+                    }
+                }
+            }
+        }
+        let mut camera = vec![false; self.columns * self.rows];
+        if let Some(center) = self.cameras.get(&player_id) {
+            let (min_column, min_row) =
+                self.cell((center.0 - CAMERA_HALF_SIZE.0, center.1 - CAMERA_HALF_SIZE.1));
+            let (max_column, max_row) =
+                self.cell((center.0 + CAMERA_HALF_SIZE.0, center.1 + CAMERA_HALF_SIZE.1));
+            for row in min_row..=max_row {
+                camera[row * self.columns + min_column..=row * self.columns + max_column]
+                    .fill(true);
+            }
+        }
+        (visible, camera)
+    }
+
+    /// Adds the cells currently visible to a player to the cells they have scouted.
+    /// Returns the RGBA pixels of what the player sees now and of what they have ever seen.
+    /// In the current vision the cells around the camera are drawn fainter when not visible and
+    /// more opaque when visible. The rows are flipped so that the north of the map is at the top.
+    pub fn update(&mut self, player_id: u8, color: [u8; 4]) -> (Vec<u8>, Vec<u8>) {
+        let (visible, camera) = self.visible_cells(player_id);
+        let scouted = self
+            .scouted
+            .entry(player_id)
+            .or_insert_with(|| vec![false; visible.len()]);
+        for (scouted, visible) in scouted.iter_mut().zip(visible.iter()) {
+            *scouted |= *visible;
+        }
+        let mut visible_pixels = Vec::with_capacity(visible.len() * 4);
+        let mut scouted_pixels = Vec::with_capacity(visible.len() * 4);
+        for row in (0..self.rows).rev() {
+            for cell in row * self.columns..(row + 1) * self.columns {
+                let alpha = match (visible[cell], camera[cell]) {
+                    (true, true) => 0xa0,
+                    (true, false) => 0x60,
+                    (false, true) => 0x30,
+                    (false, false) => 0x00,
+                };
+                visible_pixels.extend_from_slice(&[color[0], color[1], color[2], alpha]);
+                let alpha = if scouted[cell] { 0x60 } else { 0x00 };
+                scouted_pixels.extend_from_slice(&[color[0], color[1], color[2], alpha]);
+            }
+        }
+        (visible_pixels, scouted_pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The alpha of the pixel of a cell, the rows of the pixels are flipped.
+    fn alpha(vision_map: &VisionMap, pixels: &[u8], cell: (usize, usize)) -> u8 {
+        let row = vision_map.rows - 1 - cell.1;
+        pixels[(row * vision_map.columns + cell.0) * 4 + 3]
+    }
+
+    const COLOR: [u8; 4] = [255, 0, 0, 255];
+
+    #[test]
+    fn it_shows_the_cells_in_the_sight_of_the_units() {
+        let mut vision_map = VisionMap::new((32, 32), 4.);
+        assert_eq!(vision_map.resolution(), [8, 8]);
+        vision_map.set_unit(1, 1, (2., 2.), 5.);
+        let (visible, scouted) = vision_map.update(1, COLOR);
+        assert_eq!(alpha(&vision_map, &visible, (0, 0)), 0x60);
+        assert_eq!(alpha(&vision_map, &visible, (1, 0)), 0x60);
+        // The center of the cell is out of the sight radius.
+        assert_eq!(alpha(&vision_map, &visible, (1, 1)), 0x00);
+        assert_eq!(alpha(&vision_map, &visible, (7, 7)), 0x00);
+        assert_eq!(alpha(&vision_map, &scouted, (0, 0)), 0x60);
+        // The units of the other players do not grant vision.
+        let (visible, _) = vision_map.update(2, COLOR);
+        assert!(visible.chunks(4).all(|pixel| pixel[3] == 0));
+    }
+
+    #[test]
+    fn it_keeps_the_scouted_cells_after_the_unit_leaves_or_dies() {
+        let mut vision_map = VisionMap::new((32, 32), 4.);
+        vision_map.set_unit(1, 1, (2., 2.), 1.);
+        vision_map.update(1, COLOR);
+        vision_map.set_unit(1, 1, (30., 30.), 1.);
+        let (visible, scouted) = vision_map.update(1, COLOR);
+        assert_eq!(alpha(&vision_map, &visible, (0, 0)), 0x00);
+        assert_eq!(alpha(&vision_map, &visible, (7, 7)), 0x60);
+        assert_eq!(alpha(&vision_map, &scouted, (0, 0)), 0x60);
+        vision_map.remove_unit(1);
+        let (visible, scouted) = vision_map.update(1, COLOR);
+        assert!(visible.chunks(4).all(|pixel| pixel[3] == 0));
+        assert_eq!(alpha(&vision_map, &scouted, (0, 0)), 0x60);
+        assert_eq!(alpha(&vision_map, &scouted, (7, 7)), 0x60);
+        assert_eq!(alpha(&vision_map, &scouted, (3, 3)), 0x00);
+    }
+
+    #[test]
+    fn it_shows_the_camera_without_scouting_it() {
+        let mut vision_map = VisionMap::new((32, 32), 4.);
+        vision_map.set_camera(1, (16., 16.));
+        vision_map.set_unit(1, 1, (18., 18.), 1.);
+        let (visible, scouted) = vision_map.update(1, COLOR);
+        // The camera covers from (11, 11) to (21, 21), the cells 2 to 5.
+        assert_eq!(alpha(&vision_map, &visible, (2, 2)), 0x30);
+        assert_eq!(alpha(&vision_map, &visible, (5, 5)), 0x30);
+        assert_eq!(alpha(&vision_map, &visible, (4, 4)), 0xa0);
+        assert_eq!(alpha(&vision_map, &visible, (1, 2)), 0x00);
+        assert_eq!(alpha(&vision_map, &visible, (6, 5)), 0x00);
+        assert_eq!(alpha(&vision_map, &scouted, (2, 2)), 0x00);
+        assert_eq!(alpha(&vision_map, &scouted, (4, 4)), 0x60);
+    }
+
+    #[test]
+    fn it_takes_the_changed_players_once_every_step() {
+        let mut vision_map = VisionMap::new((32, 32), 4.);
+        vision_map.set_unit(1, 1, (2., 2.), 5.);
+        vision_map.set_camera(2, (16., 16.));
+        assert!(vision_map.take_changed_players(50, 100).is_empty());
+        assert_eq!(
+            vision_map.take_changed_players(100, 100),
+            BTreeSet::from([1, 2])
+        );
+        // Nothing changed, setting the same unit sight and camera again is not a change.
+        vision_map.set_unit(1, 1, (2., 2.), 5.);
+        vision_map.set_camera(2, (16., 16.));
+        assert!(vision_map.take_changed_players(200, 100).is_empty());
+        // The changes wait for the next step, counted from the last time they were taken.
+        vision_map.set_unit(1, 1, (3., 2.), 5.);
+        assert!(vision_map.take_changed_players(250, 100).is_empty());
+        assert_eq!(
+            vision_map.take_changed_players(300, 100),
+            BTreeSet::from([1])
+        );
+        // A unit with no sight is forgotten.
+        vision_map.set_unit(1, 1, (3., 2.), 0.);
+        assert_eq!(
+            vision_map.take_changed_players(400, 100),
+            BTreeSet::from([1])
+        );
+    }
+}
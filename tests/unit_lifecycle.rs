@@ -0,0 +1,142 @@
+use rerun::external::re_log_types::LogMsg;
+use rerun::log::{Chunk, RowId};
+use rerun::time::TimeInt;
+use rerun::{Component, RecordingStreamBuilder, Timeline};
+use s2protocol::state::SC2EventIterator;
+use s2protocol::tracker_events::{unit_tag, ReplayTrackerEvent};
+use s2protocol::{SC2EventType, SC2ReplayFilters};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use swarmy::*;
+
+/// Returns the game loop at which each unit dies in the replay, by unit tag.
+fn dead_unit_loops(file_path: &str) -> HashMap<i64, i64> {
+    SC2EventIterator::new(&PathBuf::from(file_path))
+        .unwrap()
+        .filter_map(|(event, _change_hint)| match event {
+            SC2EventType::Tracker {
+                tracker_loop,
+                event: ReplayTrackerEvent::UnitDied(unit_dead),
+            } => Some((
+                unit_tag(unit_dead.unit_tag_index, unit_dead.unit_tag_recycle),
+                tracker_loop,
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the unit tag of a unit entity, i.e. `Player/1/Army/11010049/Position` or
+/// `Map2D/Player/1/Army/11010049/Position`
+fn entity_unit_tag(entity_path: &str) -> Option<i64> {
+    let entity_path = entity_path.trim_start_matches("/");
+    let entity_path = entity_path.strip_prefix("Map2D/").unwrap_or(entity_path);
+    let mut parts = entity_path.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("Player"), Some(_player_id), Some(_category), Some(unit_tag)) => {
+            unit_tag.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/// The rows recorded for a unit entity on the game_loop timeline.
+#[derive(Debug, Default)]
+struct EntityRows {
+    unit_tag: i64,
+    clears: Vec<(TimeInt, RowId)>,
+    data: Vec<(TimeInt, RowId)>,
+}
+
+/// Collects the Clear and data rows of the unit entities from the recorded messages.
+fn unit_entity_rows(msgs: Vec<LogMsg>) -> HashMap<String, EntityRows> {
+    let game_loop = *Timeline::new_sequence("game_loop").name();
+    let clear_is_recursive = rerun::components::ClearIsRecursive::name();
+    let mut entity_rows: HashMap<String, EntityRows> = HashMap::new();
+    for msg in msgs {
+        let LogMsg::ArrowMsg(_store_id, arrow_msg) = msg else {
+            continue;
+        };
+        let chunk = Chunk::from_arrow_msg(&arrow_msg).unwrap();
+        let entity_path = chunk.entity_path().to_string();
+        let Some(unit_tag) = entity_unit_tag(&entity_path) else {
+            continue;
+        };
+        let rows = entity_rows
+            .entry(entity_path)
+            .or_insert_with(|| EntityRows {
+                unit_tag,
+                ..Default::default()
+            });
+        for descriptor in chunk.component_descriptors() {
+            if descriptor.component_name.is_indicator_component() {
+                continue;
+            }
+            let indices = chunk.iter_component_indices(&game_loop, &descriptor.component_name);
+            if descriptor.component_name == clear_is_recursive {
+                rows.clears.extend(indices);
+            } else {
+                rows.data.extend(indices);
+            }
+        }
+    }
+    entity_rows
+}
+
+/// Steps through the replay, every entity of the dead units should be cleared and nothing
+/// should be logged to them afterwards. The entities of a unit are cleared when it dies, or
+/// earlier when it morphs into a different category and moves to a different path.
+fn check_dead_units_are_cleared(file_path: &str) {
+    let dead_unit_loops = dead_unit_loops(file_path);
+    assert!(!dead_unit_loops.is_empty());
+    let (recording_stream, storage) = RecordingStreamBuilder::new(file_path).memory().unwrap();
+    let mut sink = RerunSink::new(recording_stream).with_render_config(
+        RenderConfig::default()
+            .with_view_mode(ViewMode::Both)
+            .with_interpolation_step_loops(Some(64)),
+    );
+    SC2Rerun::new(file_path, SC2ReplayFilters::default())
+        .unwrap()
+        .add_events(&mut sink)
+        .unwrap();
+    let entity_rows = unit_entity_rows(storage.take());
+    let mut dead_units: HashSet<i64> = HashSet::new();
+    let mut cleared_at_death: HashSet<i64> = HashSet::new();
+    for (entity_path, rows) in entity_rows {
+        let Some(death_loop) = dead_unit_loops.get(&rows.unit_tag) else {
+            continue;
+        };
+        dead_units.insert(rows.unit_tag);
+        let last_clear = rows.clears.iter().max().unwrap_or_else(|| {
+            panic!("Dead unit entity {entity_path} was never cleared");
+        });
+        if last_clear.0.as_i64() >= *death_loop {
+            cleared_at_death.insert(rows.unit_tag);
+        }
+        if let Some(last_data) = rows.data.iter().max() {
+            assert!(
+                last_data < last_clear,
+                "Dead unit entity {entity_path} has data at {:?} after it was cleared at {:?}",
+                last_data.0,
+                last_clear.0
+            );
+        }
+    }
+    assert!(!dead_units.is_empty());
+    for unit_tag in dead_units {
+        assert!(
+            cleared_at_death.contains(&unit_tag),
+            "No entity of the dead unit {unit_tag} was cleared when it died"
+        );
+    }
+}
+
+#[test_log::test]
+fn it_clears_dead_units_in_burrow_replay() {
+    check_dead_units_are_cleared("assets/Burrow.SC2Replay");
+}
+
+#[test_log::test]
+fn it_clears_dead_units_in_2v2_ai_replay() {
+    check_dead_units_are_cleared("assets/2023-04-08-2v2AI.SC2Replay");
+}